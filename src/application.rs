@@ -1,116 +1,200 @@
-use super::state::{State, CursorMovement, ChatMessage, MessageType, ScrollMovement};
+use super::state::{
+    State, CursorMovement, KillMovement, ChatMessage, MessageType, ScrollMovement, ConnectionInfo,
+    TailLines,
+};
 use crate::{
     state::Window,
-    terminal_events::{TerminalEventCollector},
+    config_watcher::{ConfigWatcher},
 };
-use crate::renderer::{Renderer};
+use crate::frontend::{Frontend, InputEvent, Output};
 use crate::action::{Action, Processing};
 use crate::commands::{CommandManager};
-use crate::message::{NetMessage, Chunk};
+use crate::node::{ChatNode, ChatEvent, NodeConfig};
 use crate::util::{Error, Result, Reportable};
 use crate::commands::send_file::{SendFileCommand};
+use crate::commands::exec::{ExecCommand, StopExecCommand};
+use crate::commands::share_term::{ShareTermCommand, StopShareTermCommand};
+use crate::commands::tail::{TailCommand, UntailCommand};
 #[cfg(feature = "stream-video")]
 use crate::commands::send_stream::{SendStreamCommand, StopStreamCommand};
-use crate::config::Config;
-use crate::encoder::{self, Encoder};
+use crate::config::{Config, EditingMode};
+use crate::history::{InputHistory};
+use crate::completion::{self};
+use crate::keybindings::{KeyAction, KeyMap};
+use crate::vi::{self, ViMode};
+#[cfg(unix)]
+use crate::control::{ControlServer, ControlRequest, Request, Subscriber};
+#[cfg(unix)]
+use crate::listen::{EventLog, Format};
 
-use crossterm::event::{Event as TermEvent, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event as TermEvent, KeyCode, KeyEvent, KeyModifiers, MouseEvent};
 
 use message_io::events::{EventReceiver};
-use message_io::network::{Endpoint, Transport};
-use message_io::node::{
-    self, StoredNodeEvent as NodeEvent, StoredNetEvent as NetEvent, NodeTask, NodeHandler,
-};
-
-use std::io::{ErrorKind};
+use message_io::node::{self, StoredNodeEvent as NodeEvent, NodeTask, NodeHandler};
 
 pub enum Signal {
-    Terminal(TermEvent),
+    Input(InputEvent),
     Action(Box<dyn Action>),
+    Chat(ChatEvent),
+    // The config file has been modified
+    ConfigChanged,
+    // Request of an external tool through the control socket
+    #[cfg(unix)]
+    Control(ControlRequest),
     // Close event with an optional error in case of failure
     // Close(None) means no error happened
     Close(Option<Error>),
 }
 
-pub struct Application<'a> {
-    config: &'a Config,
+pub struct Application {
+    config: Config,
     commands: CommandManager,
     state: State,
-    node: NodeHandler<Signal>,
+    node: ChatNode,
+    handler: NodeHandler<Signal>,
     _task: NodeTask,
     //read_file_ev: ReadFile,
-    _terminal_events: TerminalEventCollector,
+    _config_watcher: Option<ConfigWatcher>,
     receiver: EventReceiver<NodeEvent<Signal>>,
-    encoder: Encoder,
+    key_map: KeyMap,
+    // Given to the frontend before drawing
+    outputs: Vec<Output>,
+    #[cfg(unix)]
+    _control: Option<ControlServer>,
+    #[cfg(unix)]
+    subscribers: Vec<Subscriber>,
+    #[cfg(unix)]
+    event_log: EventLog,
 }
 
-impl<'a> Application<'a> {
-    pub fn new(config: &'a Config) -> Result<Application<'a>> {
+impl Application {
+    pub fn new(config: Config) -> Result<Application> {
         let (handler, listener) = node::split();
 
-        let terminal_handler = handler.clone(); // Collect terminal events
-        let _terminal_events = TerminalEventCollector::new(move |term_event| match term_event {
-            Ok(event) => terminal_handler.signals().send(Signal::Terminal(event)),
-            Err(e) => terminal_handler.signals().send(Signal::Close(Some(e))),
-        })?;
+        let _config_watcher = match &config.file_path {
+            Some(file_path) => {
+                let config_handler = handler.clone(); // Collect config file changes
+                Some(ConfigWatcher::new(file_path.clone(), move || {
+                    config_handler.signals().send(Signal::ConfigChanged)
+                })?)
+            }
+            None => None,
+        };
 
         let (_task, receiver) = listener.enqueue();
 
-        let commands = CommandManager::default().with(SendFileCommand);
+        let chat_handler = handler.clone(); // Collect chat events
+        let node = ChatNode::with_callback(NodeConfig::from(&config), move |event| {
+            chat_handler.signals().send(Signal::Chat(event))
+        })?;
+
+        let mut commands = CommandManager::default()
+            .with(SendFileCommand)
+            .with(ExecCommand)
+            .with(StopExecCommand)
+            .with(ShareTermCommand)
+            .with(StopShareTermCommand)
+            .with(TailCommand)
+            .with(UntailCommand);
         #[cfg(feature = "stream-video")]
-        let commands = commands.with(SendStreamCommand).with(StopStreamCommand);
+        {
+            commands = commands.with(SendStreamCommand).with(StopStreamCommand);
+        }
+        let (aliases, macros) = config.user_commands();
+        commands.set_user_commands(aliases, macros);
+
+        let mut state = match InputHistory::load() {
+            Ok(history) => State::new(history),
+            Err(e) => {
+                let mut state = State::default();
+                format!("Unable to load the input history: {}", e).report_warn(&mut state);
+                state
+            }
+        };
+
+        state.enable_vi_mode(config.editing_mode == EditingMode::Vi);
+        for warning in &config.warnings {
+            warning.clone().report_warn(&mut state);
+        }
+        state.set_connection(ConnectionInfo {
+            user_name: config.user_name.clone(),
+            server_addr: node.server_addr(),
+            discovery_addr: config.discovery_addr,
+        });
+
+        #[cfg(unix)]
+        let _control = match config.control_socket {
+            false => None,
+            true => {
+                let control_handler = handler.clone(); // Collect the requests of external tools
+                let control = ControlServer::new(&config.user_name, move |request| {
+                    control_handler.signals().send(Signal::Control(request))
+                });
+                match control {
+                    Ok(control) => {
+                        format!("Control socket listening in '{}'", control.path().display())
+                            .report_info(&mut state);
+                        Some(control)
+                    }
+                    Err(e) => {
+                        format!("Unable to create the control socket: {}", e)
+                            .report_warn(&mut state);
+                        None
+                    }
+                }
+            }
+        };
 
         Ok(Application {
+            key_map: config.keys.key_map()?,
+            outputs: Vec::new(),
             config,
             commands,
-            state: State::default(),
-            node: handler,
+            state,
+            node,
+            handler,
             _task,
-            // Stored because we need its internal thread running until the Application was dropped
-            _terminal_events,
+            _config_watcher,
             receiver,
-            encoder: Encoder::new(),
+            #[cfg(unix)]
+            _control,
+            #[cfg(unix)]
+            subscribers: Vec::new(),
+            #[cfg(unix)]
+            event_log: EventLog::new(),
         })
     }
 
-    pub fn run(&mut self, out: impl std::io::Write) -> Result<()> {
-        let mut renderer = Renderer::new(out)?;
-        renderer.render(&self.state, &self.config.theme)?;
-
-        let server_addr = ("0.0.0.0", self.config.tcp_server_port);
-        let (_, server_addr) = self.node.network().listen(Transport::FramedTcp, server_addr)?;
-        self.node.network().listen(Transport::Udp, self.config.discovery_addr)?;
-
-        let (discovery_endpoint, _) =
-            self.node.network().connect_sync(Transport::Udp, self.config.discovery_addr)?;
-        let message = NetMessage::HelloLan(self.config.user_name.clone(), server_addr.port());
-        self.node.network().send(discovery_endpoint, self.encoder.encode(message));
+    pub fn run(&mut self, mut frontend: impl Frontend) -> Result<()> {
+        let input_handler = self.handler.clone(); // Collect the input of the user
+        let input = move |input| input_handler.signals().send(Signal::Input(input));
+        frontend.start(&self.config, Box::new(input))?;
+        frontend.draw(&mut self.state, &self.config)?;
 
         loop {
             match self.receiver.receive() {
-                NodeEvent::Network(net_event) => match net_event {
-                    NetEvent::Connected(_, _) => { /* handler in the connect call*/ }
-                    NetEvent::Message(endpoint, message) => match encoder::decode(&message) {
-                        Some(net_message) => self.process_network_message(endpoint, net_message),
-                        None => return Err("Unknown message received".into()),
-                    },
-                    NetEvent::Accepted(_endpoint, _resource_id) => (),
-                    NetEvent::Disconnected(endpoint) => {
-                        self.state.disconnected_user(endpoint);
-                        //If the endpoint was sending a stream make sure to close its window
-                        self.state.windows.remove(&endpoint);
-                        self.righ_the_bell();
-                    }
-                },
+                NodeEvent::Network(_) => { /* The network is processed by the ChatNode */ }
                 NodeEvent::Signal(signal) => match signal {
-                    Signal::Terminal(term_event) => {
-                        self.process_terminal_event(term_event);
+                    Signal::Input(input) => {
+                        self.process_input_event(input);
                     }
                     Signal::Action(action) => {
                         self.process_action(action);
                     }
+                    Signal::ConfigChanged => {
+                        self.reload_config();
+                    }
+                    Signal::Chat(event) => {
+                        #[cfg(unix)]
+                        self.notify_subscribers(&event);
+                        self.process_chat_event(event);
+                    }
+                    #[cfg(unix)]
+                    Signal::Control(request) => {
+                        self.process_control_request(request);
+                    }
                     Signal::Close(error) => {
-                        self.node.stop();
+                        self.handler.stop();
                         return match error {
                             Some(error) => Err(error),
                             None => Ok(()),
@@ -118,195 +202,403 @@ impl<'a> Application<'a> {
                     }
                 },
             }
-            renderer.render(&self.state, &self.config.theme)?;
+            for output in self.outputs.drain(..) {
+                frontend.output(output)?;
+            }
+            frontend.draw(&mut self.state, &self.config)?;
         }
-        //Renderer is destroyed here and the terminal is recovered
+        //The frontend is destroyed here and the terminal is recovered
     }
 
-    fn process_network_message(&mut self, endpoint: Endpoint, message: NetMessage) {
-        match message {
-            // by udp (multicast):
-            NetMessage::HelloLan(user, server_port) => {
-                let server_addr = (endpoint.addr().ip(), server_port);
-                if user != self.config.user_name {
-                    let mut try_connect = || -> Result<()> {
-                        let (user_endpoint, _) =
-                            self.node.network().connect_sync(Transport::FramedTcp, server_addr)?;
-                        let message = NetMessage::HelloUser(self.config.user_name.clone());
-                        self.node.network().send(user_endpoint, self.encoder.encode(message));
-                        self.state.connected_user(user_endpoint, &user);
-                        Ok(())
-                    };
-                    try_connect().report_if_err(&mut self.state);
-                }
+    /// Apply the settings of the modified config file that can change while running
+    fn reload_config(&mut self) {
+        let mut config = match self.config.reload() {
+            Ok(config) => config,
+            Err(e) => return format!("Config file not applied: {}", e).report_err(&mut self.state),
+        };
+
+        for warning in &config.warnings {
+            warning.clone().report_warn(&mut self.state);
+        }
+
+        // Safe unwrap, the reload already checked the key bindings
+        self.key_map = config.keys.key_map().unwrap();
+        self.state.enable_vi_mode(config.editing_mode == EditingMode::Vi);
+        let (aliases, macros) = config.user_commands();
+        self.commands.set_user_commands(aliases, macros);
+
+        // The network and the terminal are already set up with the previous values
+        let restart_changes = [
+            ("discovery_addr", config.discovery_addr != self.config.discovery_addr),
+            ("tcp_server_port", config.tcp_server_port != self.config.tcp_server_port),
+            ("user_name", config.user_name != self.config.user_name),
+            ("mouse", config.mouse != self.config.mouse),
+        ];
+        let restart_fields = restart_changes
+            .iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(field, _)| *field)
+            .collect::<Vec<_>>();
+        if !restart_fields.is_empty() {
+            format!("Changes in {} will be applied after restarting", restart_fields.join(", "))
+                .report_warn(&mut self.state);
+        }
+        config.discovery_addr = self.config.discovery_addr;
+        config.tcp_server_port = self.config.tcp_server_port;
+        config.user_name = std::mem::take(&mut self.config.user_name);
+        config.mouse = self.config.mouse;
+
+        self.config = config;
+        String::from("Config file reloaded").report_info(&mut self.state);
+    }
+
+    fn process_chat_event(&mut self, event: ChatEvent) {
+        match event {
+            ChatEvent::Connected(peer) => {
+                self.state.connected_user(peer.endpoint, &peer.name);
+                self.righ_the_bell();
             }
-            // by tcp:
-            NetMessage::HelloUser(user) => {
-                self.state.connected_user(endpoint, &user);
+            ChatEvent::Disconnected(peer) => {
+                self.state.disconnected_user(peer.endpoint);
+                //If the endpoint was sending a stream make sure to close its window
+                self.state.windows.remove(&peer.endpoint);
+                self.state.terminals.remove(&peer.endpoint);
                 self.righ_the_bell();
             }
-            NetMessage::UserMessage(content) => {
-                if let Some(user) = self.state.user_name(endpoint) {
-                    let message = ChatMessage::new(user.into(), MessageType::Text(content));
-                    self.state.add_message(message);
-                    self.righ_the_bell();
-                }
+            ChatEvent::Message(peer, content) => {
+                let message = ChatMessage::new(peer.name, MessageType::Text(content));
+                self.state.add_message(message);
+                self.righ_the_bell();
             }
-            NetMessage::UserData(file_name, chunk) => {
-                use std::io::Write;
-                if self.state.user_name(endpoint).is_some() {
-                    // safe unwrap due to check
-                    let user = self.state.user_name(endpoint).unwrap().to_owned();
-
-                    match chunk {
-                        Chunk::Error => {
-                            format!("'{}' had an error while sending '{}'", user, file_name)
-                                .report_err(&mut self.state);
-                        }
-                        Chunk::End => {
-                            format!(
-                                "Successfully received file '{}' from user '{}'!",
-                                file_name, user
-                            )
-                            .report_info(&mut self.state);
-                            self.righ_the_bell();
-                        }
-                        Chunk::Data(data) => {
-                            let try_write = || -> Result<()> {
-                                let user_path = std::env::temp_dir().join("termchat").join(&user);
-                                match std::fs::create_dir_all(&user_path) {
-                                    Ok(_) => (),
-                                    Err(ref err) if err.kind() == ErrorKind::AlreadyExists => (),
-                                    Err(e) => return Err(e.into()),
-                                }
-
-                                let file_path = user_path.join(file_name);
-                                std::fs::OpenOptions::new()
-                                    .create(true)
-                                    .append(true)
-                                    .open(file_path)?
-                                    .write_all(&data)?;
-
-                                Ok(())
-                            };
-
-                            try_write().report_if_err(&mut self.state);
-                        }
-                    }
-                }
+            ChatEvent::FileReceiving(peer, file_name) => {
+                self.state.start_receiving_file(peer.endpoint, &file_name);
+            }
+            ChatEvent::FileReceived(peer, file_path) => {
+                let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+                self.state.stop_receiving_file(peer.endpoint, &file_name);
+                self.state.add_file_received_message(&peer.name, file_path);
+                self.righ_the_bell();
+            }
+            ChatEvent::FileReceiveError(peer, file_name) => {
+                self.state.stop_receiving_file(peer.endpoint, &file_name);
+                format!("'{}' had an error while sending '{}'", peer.name, file_name)
+                    .report_err(&mut self.state);
             }
-            NetMessage::Stream(data) => match data {
+            ChatEvent::FileProgress(id, bytes) => self.state.file_transfer_progress(id, bytes),
+            ChatEvent::FileSent(id) => self.state.file_transfer_sent(id),
+            ChatEvent::FileSendError(id, error) => {
+                self.state.file_transfer_end(id);
+                format!("Error sending file. error: {}", error).report_err(&mut self.state);
+            }
+            ChatEvent::Stream(peer, data) => match data {
                 Some((data, width, height)) if data.len() == width * height / 2 => {
                     self.state
                         .windows
-                        .entry(endpoint)
+                        .entry(peer.endpoint)
                         .or_insert_with(|| Window::new(width, height));
-                    self.state.update_window(&endpoint, data, width, height);
+                    self.state.update_window(&peer.endpoint, data, width, height);
                 }
                 _ => {
-                    self.state.windows.remove(&endpoint);
+                    self.state.windows.remove(&peer.endpoint);
                 }
             },
+            ChatEvent::TailLines(peer, file_name, lines, skipped_lines) => {
+                let tail = TailLines { file_name, lines, skipped_lines };
+                let message = ChatMessage::new(peer.name, MessageType::TailLines(tail));
+                self.state.add_message(message);
+            }
+            ChatEvent::TermScreen(peer, screen) => {
+                self.state.update_shared_terminal(peer.endpoint, screen);
+            }
+            ChatEvent::Error(error) => error.report_err(&mut self.state),
+        }
+    }
+
+    fn process_input_event(&mut self, input: InputEvent) {
+        match input {
+            InputEvent::Terminal(term_event) => self.process_terminal_event(term_event),
+            InputEvent::SelectMessage(message) => self.state.select_message(message),
+            InputEvent::Line(line) => {
+                self.state.input_insert(&line);
+                self.send_input();
+            }
+            InputEvent::Close(error) => {
+                self.handler.signals().send_with_priority(Signal::Close(error))
+            }
         }
     }
 
     fn process_terminal_event(&mut self, term_event: TermEvent) {
         match term_event {
-            TermEvent::Mouse(_) => (),
+            TermEvent::Mouse(mouse_event) => self.process_mouse_event(mouse_event),
             TermEvent::Resize(_, _) => (),
-            TermEvent::Key(KeyEvent { code, modifiers }) => match code {
-                KeyCode::Esc => {
-                    self.node.signals().send_with_priority(Signal::Close(None));
+            TermEvent::Key(key) => {
+                let action = self.key_map.action(key);
+                if self.state.history_search().is_some()
+                    && self.process_history_search_key(key, action)
+                {
+                    return
                 }
-                KeyCode::Char(character) => {
-                    if character == 'c' && modifiers.contains(KeyModifiers::CONTROL) {
-                        self.node.signals().send_with_priority(Signal::Close(None));
+
+                let plain = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                match (self.state.vi_mode(), key.code) {
+                    (Some(ViMode::Insert), KeyCode::Esc) if plain => {
+                        self.state.input_completion_end();
+                        return vi::enter_normal_mode(&mut self.state)
                     }
-                    else {
-                        self.state.input_write(character);
+                    (Some(ViMode::Normal), KeyCode::Char(character)) if plain => {
+                        self.state.input_completion_end();
+                        return vi::process_normal_char(&mut self.state, character)
                     }
+                    _ => (),
                 }
-                KeyCode::Enter => {
-                    if let Some(input) = self.state.reset_input() {
-                        match self.commands.find_command_action(&input).transpose() {
-                            Ok(action) => {
-                                let message = ChatMessage::new(
-                                    format!("{} (me)", self.config.user_name),
-                                    MessageType::Text(input.clone()),
-                                );
-                                self.state.add_message(message);
-
-                                for endpoint in self.state.all_user_endpoints() {
-                                    self.node.network().send(
-                                        *endpoint,
-                                        self.encoder.encode(NetMessage::UserMessage(input.clone())),
-                                    );
-                                }
-
-                                match action {
-                                    Some(action) => self.process_action(action),
-                                    None => {
-                                        if input.starts_with('?') {
-                                            String::from("This command doesn't exists")
-                                                .report_err(&mut self.state);
-                                        }
-                                    }
-                                }
-                            }
-                            Err(error) => {
-                                error.report_err(&mut self.state);
+
+                match action {
+                    Some(action) => self.process_key_action(action),
+                    None => {
+                        if let KeyCode::Char(character) = key.code {
+                            if plain {
+                                self.state.input_completion_end();
+                                self.state.input_write(character);
                             }
-                        };
+                        }
                     }
                 }
-                KeyCode::Delete => {
-                    self.state.input_remove();
-                }
-                KeyCode::Backspace => {
-                    self.state.input_remove_previous();
-                }
-                KeyCode::Left => {
-                    self.state.input_move_cursor(CursorMovement::Left);
-                }
-                KeyCode::Right => {
-                    self.state.input_move_cursor(CursorMovement::Right);
-                }
-                KeyCode::Home => {
-                    self.state.input_move_cursor(CursorMovement::Start);
-                }
-                KeyCode::End => {
-                    self.state.input_move_cursor(CursorMovement::End);
-                }
-                KeyCode::Up => {
-                    self.state.messages_scroll(ScrollMovement::Up);
-                }
-                KeyCode::Down => {
-                    self.state.messages_scroll(ScrollMovement::Down);
-                }
-                KeyCode::PageUp => {
-                    self.state.messages_scroll(ScrollMovement::Start);
+            }
+        }
+    }
+
+    fn process_mouse_event(&mut self, mouse_event: MouseEvent) {
+        match mouse_event {
+            MouseEvent::ScrollUp(..) => self.state.messages_scroll(ScrollMovement::Up),
+            MouseEvent::ScrollDown(..) => self.state.messages_scroll(ScrollMovement::Down),
+            _ => (),
+        }
+    }
+
+    /// Returns false if the key is not part of the search and must be processed as usual.
+    fn process_history_search_key(&mut self, key: KeyEvent, action: Option<KeyAction>) -> bool {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match (key.code, action) {
+            (_, Some(KeyAction::HistorySearch)) => self.state.history_search_start(),
+            (_, Some(KeyAction::DeletePrevious)) => self.state.history_search_remove_previous(),
+            (KeyCode::Char('g'), _) if control => self.state.history_search_cancel(),
+            (KeyCode::Esc, _) => self.state.history_search_cancel(),
+            (KeyCode::Char(character), None) if !control => {
+                self.state.history_search_write(character)
+            }
+            _ => {
+                self.state.history_search_accept();
+                return false
+            }
+        }
+        true
+    }
+
+    fn process_key_action(&mut self, action: KeyAction) {
+        if !matches!(action, KeyAction::Complete | KeyAction::CompletePrevious) {
+            self.state.input_completion_end();
+        }
+
+        match action {
+            KeyAction::Quit => self.handler.signals().send_with_priority(Signal::Close(None)),
+            KeyAction::Send => self.send_input(),
+            KeyAction::NewLine => self.state.input_write('\n'),
+            KeyAction::Complete => self.complete_input(true),
+            KeyAction::CompletePrevious => self.complete_input(false),
+            KeyAction::CursorLeft => self.state.input_move_cursor(CursorMovement::Left),
+            KeyAction::CursorRight => self.state.input_move_cursor(CursorMovement::Right),
+            KeyAction::CursorWordLeft => self.state.input_move_cursor(CursorMovement::WordLeft),
+            KeyAction::CursorWordRight => self.state.input_move_cursor(CursorMovement::WordRight),
+            KeyAction::CursorStart => self.state.input_move_cursor(CursorMovement::Start),
+            KeyAction::CursorEnd => self.state.input_move_cursor(CursorMovement::End),
+            KeyAction::Delete => self.state.input_remove(),
+            KeyAction::DeletePrevious => self.state.input_remove_previous(),
+            KeyAction::KillWordLeft => self.state.input_kill(KillMovement::WordLeft),
+            KeyAction::KillToStart => self.state.input_kill(KillMovement::Start),
+            KeyAction::KillToEnd => self.state.input_kill(KillMovement::End),
+            KeyAction::Yank => self.state.input_yank(),
+            KeyAction::YankPop => self.state.input_yank_pop(),
+            KeyAction::HistoryPrevious => self.state.input_history_previous(),
+            KeyAction::HistoryNext => self.state.input_history_next(),
+            KeyAction::HistorySearch => self.state.history_search_start(),
+            KeyAction::ScrollUp => self.state.messages_scroll(ScrollMovement::Up),
+            KeyAction::ScrollDown => self.state.messages_scroll(ScrollMovement::Down),
+            KeyAction::ScrollPageUp => self.state.messages_scroll(ScrollMovement::PageUp),
+            KeyAction::ScrollPageDown => self.state.messages_scroll(ScrollMovement::PageDown),
+            KeyAction::ScrollTop => self.state.messages_scroll(ScrollMovement::Top),
+            KeyAction::ScrollBottom => self.state.messages_scroll(ScrollMovement::Bottom),
+            KeyAction::ToggleVideoPanel => self.state.toggle_video_panel(),
+            KeyAction::ReplyMessage => self.reply_selected_message(),
+            KeyAction::ReactMessage => self.react_selected_message(),
+            KeyAction::CopyMessage => self.copy_selected_message(),
+            KeyAction::None => (),
+        }
+    }
+
+    fn send_input(&mut self) {
+        if let Some(input) = self.state.reset_input() {
+            self.state.messages_scroll(ScrollMovement::Bottom);
+            // Aliases and macros are expanded to the lines that they represent
+            match self.commands.expand(&input) {
+                Ok(lines) => lines
+                    .into_iter()
+                    .for_each(|line| self.process_input(line).report_if_err(&mut self.state)),
+                Err(error) => error.report_err(&mut self.state),
+            }
+        }
+    }
+
+    fn process_input(&mut self, input: String) -> Result<()> {
+        let action = self.commands.find_command_action(&input).transpose()?;
+        match self.commands.is_local(&input) {
+            true => self.add_own_message(input.clone()),
+            false => self.send_message(input.clone()),
+        }
+        self.process_command(&input, action)
+    }
+
+    /// Process the action of a command, or fail if the input looks like an unknown command
+    fn process_command(&mut self, input: &str, action: Option<Box<dyn Action>>) -> Result<()> {
+        match action {
+            Some(action) => self.process_action(action),
+            None => {
+                if input.starts_with('?') {
+                    return Err(
+                        "This command doesn't exist, use ?help to see the available ones".into()
+                    )
                 }
-                _ => (),
-            },
+            }
+        }
+        Ok(())
+    }
+
+    fn send_message(&mut self, content: String) {
+        self.node.send_message(&content);
+        self.add_own_message(content);
+    }
+
+    fn add_own_message(&mut self, content: String) {
+        let message =
+            ChatMessage::new(format!("{} (me)", self.config.user_name), MessageType::Text(content));
+        self.state.add_message(message);
+    }
+
+    fn take_selected_message(&mut self) -> Option<(String, String)> {
+        let index = self.state.selected_message()?;
+        self.state.select_message(None);
+        let message = &self.state.messages()[index];
+        Some((message.user.trim_end_matches(": ").into(), message.text()))
+    }
+
+    /// Quote the selected message in the input
+    fn reply_selected_message(&mut self) {
+        if let Some((user, text)) = self.take_selected_message() {
+            let quote = text.lines().map(|line| format!("> {}\n", line)).collect::<String>();
+            self.state.input_insert(&format!("> {}:\n{}", user, quote));
+        }
+    }
+
+    fn react_selected_message(&mut self) {
+        const MAX_QUOTE_LEN: usize = 40;
+        if let Some((user, text)) = self.take_selected_message() {
+            let line = text.lines().next().unwrap_or_default();
+            let mut quote = line.chars().take(MAX_QUOTE_LEN).collect::<String>();
+            if quote.len() < line.len() {
+                quote.push('…');
+            }
+            self.send_message(format!("👍 > {}: {}", user, quote));
+        }
+    }
+
+    /// Copy the selected message to the clipboard of the terminal
+    fn copy_selected_message(&mut self) {
+        if let Some((_, text)) = self.take_selected_message() {
+            self.outputs.push(Output::Clipboard(text));
+            String::from("Message copied to the clipboard").report_info(&mut self.state);
+        }
+    }
+
+    fn complete_input(&mut self, forward: bool) {
+        if !self.state.input_completion_cycle(forward) {
+            let input = self.state.input_before_cursor();
+            let users = self.state.user_names();
+            if let Some((start, candidates)) = completion::complete(input, &self.commands, users) {
+                self.state.input_complete(start, candidates);
+            }
         }
     }
 
     fn process_action(&mut self, mut action: Box<dyn Action>) {
-        match action.process(&mut self.state, self.node.network()) {
+        match action.process(&mut self.state, &self.node) {
             Processing::Completed => (),
             Processing::Partial(delay) => {
-                self.node.signals().send_with_timer(Signal::Action(action), delay);
+                self.handler.signals().send_with_timer(Signal::Action(action), delay);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn process_control_request(&mut self, control: ControlRequest) {
+        let ControlRequest { request, client } = control;
+        let reply = match request {
+            Request::Send { message } if message.is_empty() => client.reply_error("Empty message"),
+            Request::Send { message } => {
+                self.send_message(message);
+                client.reply_ok()
             }
+            Request::Command { command } => match self.run_command(&command) {
+                Ok(()) => client.reply_ok(),
+                Err(error) => {
+                    let reply = client.reply_error(&error.to_string());
+                    error.report_err(&mut self.state);
+                    reply
+                }
+            },
+            Request::Peers => client.reply_peers(&self.node.peers()),
+            Request::Subscribe => client.reply_ok().and_then(|()| {
+                self.subscribers.push(client.subscribe()?);
+                Ok(())
+            }),
+        };
+        // A client that doesn't read the reply has nothing else to do with it
+        reply.ok();
+    }
+
+    /// Process a command of an external tool as if the user typed it,
+    /// but the command is not shown or sent as a message
+    #[cfg(unix)]
+    fn run_command(&mut self, command: &str) -> Result<()> {
+        if !command.starts_with('?') {
+            return Err("The commands start with '?', use the send request for messages".into())
+        }
+        for line in self.commands.expand(command)? {
+            match self.commands.find_command_action(&line).transpose()? {
+                // The text written by a macro is sent as the user would do
+                None if !line.starts_with('?') => self.send_message(line),
+                action => self.process_command(&line, action)?,
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn notify_subscribers(&mut self, event: &ChatEvent) {
+        // Always computed, the log tracks the streams and the shared terminals
+        match self.event_log.line(event, Format::Json) {
+            Ok(Some(line)) => self.subscribers.retain(|subscriber| subscriber.send_event(&line)),
+            Ok(None) => (),
+            Err(error) => error.report_err(&mut self.state),
         }
     }
 
     pub fn node_handler(&self) -> NodeHandler<Signal> {
-        self.node.clone()
+        self.handler.clone()
     }
 
-    pub fn righ_the_bell(&self) {
+    pub fn righ_the_bell(&mut self) {
         if self.config.terminal_bell {
-            print!("\x07");
+            self.outputs.push(Output::Bell);
         }
     }
 }
@@ -1,43 +1,462 @@
 pub mod send_file;
 #[cfg(feature = "stream-video")]
 pub mod send_stream;
+pub mod exec;
+pub mod share_term;
+pub mod tail;
+mod help;
 
 use crate::action::{Action};
 use crate::util::{Result};
 
+use help::{ShowHelp};
+
 use std::collections::{HashMap};
 
+/// Description of a param of a command, used by the help and the usage messages
+pub struct Param {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+    /// The param takes all the remaining values
+    pub multiple: bool,
+}
+
+impl Param {
+    pub fn required(name: &'static str, description: &'static str) -> Param {
+        Param { name, description, required: true, multiple: false }
+    }
+
+    pub fn optional(name: &'static str, description: &'static str) -> Param {
+        Param { name, description, required: false, multiple: false }
+    }
+
+    pub fn multiple(self) -> Param {
+        Param { multiple: true, ..self }
+    }
+
+    fn usage(&self) -> String {
+        let dots = if self.multiple { "..." } else { "" };
+        match self.required {
+            true => format!("<{}>{}", self.name, dots),
+            false => format!("[{}]{}", self.name, dots),
+        }
+    }
+}
+
 pub trait Command {
     fn name(&self) -> &'static str;
+
+    /// What the command does, in one line
+    fn description(&self) -> &'static str;
+
+    fn params(&self) -> Vec<Param> {
+        Vec::new()
+    }
+
+    /// The command with its params, as `?send <file>`
+    fn usage(&self) -> String {
+        let mut usage = format!("{}{}", CommandManager::COMMAND_PREFIX, self.name());
+        for param in self.params() {
+            usage.push(' ');
+            usage.push_str(&param.usage());
+        }
+        usage
+    }
+
+    /// Called only with a number of params valid for the params of the command
     fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>>;
+
+    /// The params of this command are file paths, used by the tab completion
+    fn path_params(&self) -> bool {
+        false
+    }
+
+    /// The params are given as written, in one string, as the command line run by `?exec`
+    fn raw_params(&self) -> bool {
+        false
+    }
+
+    /// The written command is only shown to the user, it is not sent to the others as a message
+    fn local(&self, _param_str: &str) -> bool {
+        false
+    }
 }
 
 #[derive(Default)]
 pub struct CommandManager {
     parsers: HashMap<&'static str, Box<dyn Command + Send>>,
+    // Other names for a command, the target can include params
+    aliases: HashMap<String, String>,
+    // Input lines written by a command, with `$1`, `$2`... replaced by its params
+    macros: HashMap<String, Vec<String>>,
 }
 
 impl CommandManager {
     pub const COMMAND_PREFIX: &'static str = "?";
+    pub const HELP_COMMAND: &'static str = "help";
+    const MAX_EXPANSION_DEPTH: usize = 8;
 
     pub fn with(mut self, command_parser: impl Command + 'static + Send) -> Self {
         self.parsers.insert(command_parser.name(), Box::new(command_parser));
         self
     }
 
-    pub fn find_command_action(&self, input: &str) -> Option<Result<Box<dyn Action>>> {
-        if let Some(input) = input.strip_prefix(Self::COMMAND_PREFIX) {
-            let mut input = input.splitn(2, char::is_whitespace);
-            if let Some(first) = input.next() {
-                if let Some(parser) = self.parsers.get(first) {
-                    let param_str = input.next().unwrap_or("");
-                    return match shellwords::split(param_str) {
-                        Ok(params) => Some(parser.parse_params(params)),
-                        Err(err) => Some(Err(err.into())),
+    /// Aliases and macros defined by the user. The built-in commands take precedence over them.
+    pub fn set_user_commands(
+        &mut self,
+        aliases: HashMap<String, String>,
+        macros: HashMap<String, Vec<String>>,
+    ) {
+        self.aliases = aliases;
+        self.macros = macros;
+    }
+
+    pub fn command_names(&self) -> impl Iterator<Item = &str> + '_ {
+        std::iter::once(Self::HELP_COMMAND)
+            .chain(self.parsers.keys().copied())
+            .chain(self.aliases.keys().map(|name| name.as_str()))
+            .chain(self.macros.keys().map(|name| name.as_str()))
+    }
+
+    pub fn expects_paths(&self, name: &str) -> bool {
+        match self.aliases.get(name) {
+            Some(target) if !self.parsers.contains_key(name) => {
+                let target = target.split_whitespace().next().unwrap_or_default();
+                self.parsers.get(target).map(|parser| parser.path_params()).unwrap_or(false)
+            }
+            _ => self.parsers.get(name).map(|parser| parser.path_params()).unwrap_or(false),
+        }
+    }
+
+    fn split_command(input: &str) -> Option<(&str, &str)> {
+        let mut input = input.strip_prefix(Self::COMMAND_PREFIX)?.splitn(2, char::is_whitespace);
+        Some((input.next()?, input.next().unwrap_or("")))
+    }
+
+    /// Replace an alias by its command and a macro by its lines, recursively.
+    /// The input lines to process are returned, only the same input if there is nothing to expand.
+    pub fn expand(&self, input: &str) -> Result<Vec<String>> {
+        self.expand_nested(input, 0)
+    }
+
+    fn expand_nested(&self, input: &str, depth: usize) -> Result<Vec<String>> {
+        let (name, param_str) = match Self::split_command(input) {
+            Some((name, _)) if name == Self::HELP_COMMAND || self.parsers.contains_key(name) => {
+                return Ok(vec![input.into()])
+            }
+            Some(command) => command,
+            None => return Ok(vec![input.into()]),
+        };
+
+        if depth == Self::MAX_EXPANSION_DEPTH {
+            return Err(format!("Too many nested aliases or macros expanding '{}'", name).into())
+        }
+
+        if let Some(target) = self.aliases.get(name) {
+            let command = match param_str.trim().is_empty() {
+                true => format!("{}{}", Self::COMMAND_PREFIX, target),
+                false => format!("{}{} {}", Self::COMMAND_PREFIX, target, param_str.trim()),
+            };
+            return self.expand_nested(&command, depth + 1)
+        }
+
+        if let Some(lines) = self.macros.get(name) {
+            let params = shellwords::split(param_str)?;
+            if params.len() < macro_params_count(lines) {
+                return Err(format!("Usage: {}", macro_usage(name, lines)).into())
+            }
+            let mut expanded = Vec::new();
+            for line in lines {
+                match line.starts_with(Self::COMMAND_PREFIX) {
+                    true => {
+                        // Each param keeps being one param in the commands written by the macro
+                        let params: Vec<_> =
+                            params.iter().map(|param| shellwords::escape(param)).collect();
+                        let line = replace_macro_params(line, &params);
+                        expanded.extend(self.expand_nested(&line, depth + 1)?);
+                    }
+                    false => {
+                        // A text line is never a command, even if a param makes it look like one
+                        let line = replace_macro_params(line, &params);
+                        match line.starts_with(Self::COMMAND_PREFIX) {
+                            true => expanded.push(format!(" {}", line)),
+                            false => expanded.push(line),
+                        }
                     }
                 }
             }
+            return Ok(expanded)
+        }
+
+        Ok(vec![input.into()])
+    }
+
+    /// The input is a command only shown to the user, as `?help` or `?exec` without `--share`
+    pub fn is_local(&self, input: &str) -> bool {
+        match Self::split_command(input) {
+            Some((name, _)) if name == Self::HELP_COMMAND => true,
+            Some((name, param_str)) => {
+                self.parsers.get(name).is_some_and(|parser| parser.local(param_str))
+            }
+            None => false,
+        }
+    }
+
+    pub fn find_command_action(&self, input: &str) -> Option<Result<Box<dyn Action>>> {
+        let (name, param_str) = Self::split_command(input)?;
+        if name == Self::HELP_COMMAND {
+            return Some(self.help(param_str.trim()))
+        }
+        let parser = self.parsers.get(name)?;
+        let params = match parser.raw_params() {
+            true => Ok(Some(param_str.trim())
+                .filter(|params| !params.is_empty())
+                .into_iter()
+                .map(String::from)
+                .collect()),
+            false => shellwords::split(param_str),
+        };
+        match params {
+            Ok(params) if Self::valid_params_count(parser.as_ref(), &params) => {
+                Some(parser.parse_params(params))
+            }
+            Ok(_) => Some(Err(format!("Usage: {}", parser.usage()).into())),
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+
+    fn valid_params_count(parser: &dyn Command, params: &[String]) -> bool {
+        let expected = parser.params();
+        let required = expected.iter().filter(|param| param.required).count();
+        let unlimited = expected.iter().any(|param| param.multiple);
+        params.len() >= required && (unlimited || params.len() <= expected.len())
+    }
+
+    /// `?help` lists all the commands, `?help <command>` explains one of them
+    fn help(&self, command: &str) -> Result<Box<dyn Action>> {
+        let command = command.strip_prefix(Self::COMMAND_PREFIX).unwrap_or(command);
+        if command.is_empty() {
+            let mut commands = self.parsers.values().collect::<Vec<_>>();
+            commands.sort_by_key(|parser| parser.name());
+            let mut lines = vec![String::from("Available commands:")];
+            lines.push(format!(
+                "{}{} [command]: Show the commands, or how to use one of them",
+                Self::COMMAND_PREFIX,
+                Self::HELP_COMMAND
+            ));
+            lines.extend(
+                commands
+                    .into_iter()
+                    .map(|parser| format!("{}: {}", parser.usage(), parser.description())),
+            );
+            let mut aliases = self.aliases.iter().collect::<Vec<_>>();
+            aliases.sort();
+            lines.extend(aliases.into_iter().map(|(name, target)| {
+                format!(
+                    "{}{}: Alias of {}{}",
+                    Self::COMMAND_PREFIX,
+                    name,
+                    Self::COMMAND_PREFIX,
+                    target
+                )
+            }));
+            let mut macros = self.macros.iter().collect::<Vec<_>>();
+            macros.sort();
+            lines.extend(
+                macros.into_iter().map(|(name, macro_lines)| {
+                    format!("{}: Macro", macro_usage(name, macro_lines))
+                }),
+            );
+            return Ok(Box::new(ShowHelp::new(lines.join("\n"))))
+        }
+
+        if !self.parsers.contains_key(command) {
+            if let Some(target) = self.aliases.get(command) {
+                let help = format!(
+                    "{}{} is an alias of {}{}",
+                    Self::COMMAND_PREFIX,
+                    command,
+                    Self::COMMAND_PREFIX,
+                    target
+                );
+                return Ok(Box::new(ShowHelp::new(help)))
+            }
+            if let Some(macro_lines) = self.macros.get(command) {
+                let mut lines = vec![
+                    format!("Usage: {}", macro_usage(command, macro_lines)),
+                    String::from("Macro that writes:"),
+                ];
+                lines.extend(macro_lines.iter().map(|line| format!("  {}", line)));
+                return Ok(Box::new(ShowHelp::new(lines.join("\n"))))
+            }
+        }
+
+        let parser = self.parsers.get(command).ok_or_else(|| {
+            format!(
+                "Unknown command '{}', use {}{} to see the available commands",
+                command,
+                Self::COMMAND_PREFIX,
+                Self::HELP_COMMAND
+            )
+        })?;
+        let mut lines = vec![format!("Usage: {}", parser.usage()), parser.description().into()];
+        lines.extend(
+            parser
+                .params()
+                .into_iter()
+                .map(|param| format!("  {}: {}", param.name, param.description)),
+        );
+        Ok(Box::new(ShowHelp::new(lines.join("\n"))))
+    }
+}
+
+/// Params needed by a macro: the highest `$N` used in its lines
+fn macro_params_count(lines: &[String]) -> usize {
+    let mut count = 0;
+    for line in lines {
+        let mut chars = line.chars().peekable();
+        while let Some(character) = chars.next() {
+            if character == '$' && chars.next_if_eq(&'$').is_none() {
+                let mut number = String::new();
+                while let Some(digit) = chars.peek().filter(|digit| digit.is_ascii_digit()) {
+                    number.push(*digit);
+                    chars.next();
+                }
+                count = count.max(number.parse().unwrap_or(0));
+            }
+        }
+    }
+    count
+}
+
+fn macro_usage(name: &str, lines: &[String]) -> String {
+    let mut usage = format!("{}{}", CommandManager::COMMAND_PREFIX, name);
+    for index in 1..=macro_params_count(lines) {
+        usage.push_str(&format!(" <${}>", index));
+    }
+    usage
+}
+
+/// Replace `$1`, `$2`... by the params, `$*` by all of them, and `$$` by `$`
+fn replace_macro_params(line: &str, params: &[String]) -> String {
+    let mut replaced = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(character) = chars.next() {
+        if character != '$' {
+            replaced.push(character);
+            continue
+        }
+        match chars.peek() {
+            Some('*') => {
+                chars.next();
+                replaced.push_str(&params.join(" "));
+            }
+            Some('$') => {
+                chars.next();
+                replaced.push('$');
+            }
+            Some(digit) if digit.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(digit) = chars.peek().filter(|digit| digit.is_ascii_digit()) {
+                    number.push(*digit);
+                    chars.next();
+                }
+                let index = number.parse::<usize>().ok().and_then(|index| index.checked_sub(1));
+                if let Some(param) = index.and_then(|index| params.get(index)) {
+                    replaced.push_str(param);
+                }
+            }
+            _ => replaced.push('$'),
         }
-        None
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use send_file::{SendFileCommand};
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn manager(aliases: &[(&str, &str)], macros: &[(&str, &[&str])]) -> CommandManager {
+        let aliases = aliases.iter().map(|(name, target)| (name.to_string(), target.to_string()));
+        let macros = macros.iter().map(|(name, macro_lines)| (name.to_string(), lines(macro_lines)));
+        let mut manager = CommandManager::default().with(SendFileCommand);
+        manager.set_user_commands(aliases.collect(), macros.collect());
+        manager
+    }
+
+    #[test]
+    fn alias_expansion() {
+        let manager = manager(&[("sf", "send"), ("sfa", "sf a.txt")], &[]);
+        assert_eq!(manager.expand("?sf b.txt").unwrap(), lines(&["?send b.txt"]));
+        assert_eq!(manager.expand("?sfa").unwrap(), lines(&["?send a.txt"]));
+        assert_eq!(manager.expand("?sfa b.txt").unwrap(), lines(&["?send a.txt b.txt"]));
+        assert_eq!(manager.expand("hi ?sf").unwrap(), lines(&["hi ?sf"]));
+        assert_eq!(manager.expand("?unknown").unwrap(), lines(&["?unknown"]));
+        assert!(manager.expects_paths("sf"));
+        assert!(!manager.expects_paths("unknown"));
+    }
+
+    #[test]
+    fn builtin_commands_over_user_commands() {
+        let manager = manager(&[("send", "help")], &[("help", &["hi"])]);
+        assert_eq!(manager.expand("?send a.txt").unwrap(), lines(&["?send a.txt"]));
+        assert_eq!(manager.expand("?help send").unwrap(), lines(&["?help send"]));
+    }
+
+    #[test]
+    fn macro_expansion() {
+        let manager = manager(
+            &[("sf", "send")],
+            &[("share", &["Sharing $1 for $$$2", "?sf $1"]), ("say", &["$* $2"])],
+        );
+        let expanded = manager.expand("?share 'my file' 5").unwrap();
+        assert_eq!(expanded, lines(&["Sharing my file for $5", "?send my\\ file"]));
+        assert_eq!(manager.expand("?say a b").unwrap(), lines(&["a b b"]));
+
+        let error = manager.expand("?share a").unwrap_err().to_string();
+        assert_eq!(error, "Usage: ?share <$1> <$2>");
+    }
+
+    #[test]
+    fn macro_params_written_as_text() {
+        let manager = manager(&[], &[("say", &["$1 hi"]), ("echo", &["$*"])]);
+        let expanded = manager.expand("?say '?exec rm -rf ~'").unwrap();
+        assert_eq!(expanded, lines(&[" ?exec rm -rf ~ hi"]));
+        assert_eq!(manager.expand("?echo ?say a").unwrap(), lines(&[" ?say a"]));
+        assert!(!manager.is_local(&expanded[0]));
+        assert!(manager.find_command_action(&expanded[0]).is_none());
+    }
+
+    #[test]
+    fn recursive_user_commands() {
+        let manager = manager(&[("a", "b"), ("b", "a")], &[("m", &["?m"])]);
+        let error = manager.expand("?a").unwrap_err().to_string();
+        assert!(error.starts_with("Too many nested aliases or macros"));
+        assert!(manager.expand("?m").is_err());
+    }
+
+    #[test]
+    fn macro_params() {
+        assert_eq!(macro_params_count(&lines(&["$2 $1", "$$3 $*", "$10$"])), 10);
+        assert_eq!(macro_params_count(&lines(&["no params $"])), 0);
+        let params = lines(&["a", "b"]);
+        assert_eq!(replace_macro_params("$2$1 $3 $0 $* $$1 $x $", &params), "ba   a b $1 $x $");
+    }
+
+    #[test]
+    fn missing_command_params() {
+        let manager = manager(&[], &[]);
+        let error = manager.find_command_action("?send").unwrap().err().unwrap();
+        assert!(error.to_string().starts_with("Usage: ?send"));
+        let error = manager.find_command_action("?send a.txt b.txt").unwrap().err().unwrap();
+        assert!(error.to_string().starts_with("Usage: ?send"));
+        assert!(manager.find_command_action("?unknown").is_none());
+        assert!(manager.find_command_action("no command").is_none());
     }
 }
@@ -0,0 +1,324 @@
+use crate::action::{Action, Processing};
+use crate::commands::{Command, Param};
+use crate::state::{State};
+use crate::util::{Result, Reportable};
+
+use crate::node::{ChatNode};
+
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::time::{Duration};
+
+pub struct ExecCommand;
+
+impl ExecCommand {
+    const SHARE_FLAG: &'static str = "--share";
+
+    /// Whether the output is shared, and the command line without the flag
+    fn split_share_flag(param_str: &str) -> (bool, &str) {
+        let param_str = param_str.trim();
+        match param_str.strip_prefix(Self::SHARE_FLAG) {
+            Some(rest) if rest.starts_with(char::is_whitespace) => (true, rest.trim_start()),
+            _ => (false, param_str),
+        }
+    }
+}
+
+impl Command for ExecCommand {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a shell command and show its output, or send it to all the users with --share"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param::optional(Self::SHARE_FLAG, "Send the output to all the users when it finishes"),
+            Param::required("command", "Command line run by the shell").multiple(),
+        ]
+    }
+
+    fn raw_params(&self) -> bool {
+        true
+    }
+
+    fn local(&self, param_str: &str) -> bool {
+        !Self::split_share_flag(param_str).0
+    }
+
+    fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>> {
+        let command_line = params.first().ok_or_else(|| format!("Usage: {}", self.usage()))?;
+        let (share, command_line) = Self::split_share_flag(command_line);
+        Ok(Box::new(Exec::new(command_line, share)))
+    }
+}
+
+pub struct Exec {
+    command_line: String,
+    share: bool,
+    // Started by the first process, not while the command is parsed
+    running: Option<RunningCommand>,
+    output_id: Option<usize>,
+    generation: usize,
+}
+
+struct RunningCommand {
+    child: Child,
+    output: Receiver<(String, bool)>,
+}
+
+impl Exec {
+    const MAX_LINES_PER_PROCESS: usize = 100;
+    const POLL_DELAY: Duration = Duration::from_millis(50);
+
+    pub fn new(command_line: &str, share: bool) -> Exec {
+        Exec {
+            command_line: command_line.into(),
+            share,
+            running: None,
+            output_id: None,
+            generation: 0,
+        }
+    }
+
+    fn spawn(command_line: &str) -> Result<RunningCommand> {
+        #[cfg(not(windows))]
+        let mut command = std::process::Command::new("sh");
+        #[cfg(not(windows))]
+        command.arg("-c");
+        #[cfg(windows)]
+        let mut command = std::process::Command::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+        // In its own process group, the processes started by the command can be killed with it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+        // The terminal input belongs to termchat, the command can not read from it
+        let mut child = command
+            .arg(command_line)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Unable to run '{}': {}", command_line, e))?;
+
+        let (sender, output) = mpsc::channel();
+        // Safe unwraps, both outputs were piped
+        Self::read_lines(child.stdout.take().unwrap(), sender.clone(), false)?;
+        Self::read_lines(child.stderr.take().unwrap(), sender, true)?;
+
+        Ok(RunningCommand { child, output })
+    }
+
+    /// Send each line of the output through the channel, from its own thread
+    fn read_lines(
+        output: impl Read + Send + 'static,
+        sender: Sender<(String, bool)>,
+        error: bool,
+    ) -> Result<()> {
+        std::thread::Builder::new().name("termchat: exec output reader".into()).spawn(
+            move || {
+                let mut reader = BufReader::new(output);
+                let mut line = Vec::new();
+                while let Ok(size) = reader.read_until(b'\n', &mut line) {
+                    if size == 0 {
+                        break
+                    }
+                    let content =
+                        String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).into();
+                    if sender.send((content, error)).is_err() {
+                        break
+                    }
+                    line.clear();
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self, state: &mut State, node: &ChatNode, exit: String) -> Processing {
+        // Safe unwrap, the message was created in the first process
+        let text = state.command_output_end(self.output_id.unwrap(), exit);
+        if self.share {
+            node.send_message(&text);
+        }
+        Processing::Completed
+    }
+
+    /// Called once the output is finished, the command could still be running
+    fn wait_exit(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        // Safe unwrap, the command was started in the first process
+        let exit = match self.running.as_mut().unwrap().child.try_wait() {
+            Ok(Some(status)) => match status.code() {
+                Some(code) => format!("exit code {}", code),
+                None => String::from("terminated by a signal"),
+            },
+            Ok(None) => return Processing::Partial(Self::POLL_DELAY),
+            Err(e) => format!("unknown exit: {}", e),
+        };
+        self.finish(state, node, exit)
+    }
+}
+
+impl RunningCommand {
+    /// Kill the command and the processes that it started
+    fn kill(&mut self) {
+        // The process could have already finished, killing it is only tried
+        #[cfg(unix)]
+        // A negative pid sends the signal to all the processes of the group
+        unsafe {
+            libc::kill(-(self.child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        #[cfg(not(unix))]
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Action for Exec {
+    fn process(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        let output_id = match self.output_id {
+            Some(output_id) => output_id,
+            None => {
+                match Self::spawn(&self.command_line) {
+                    Ok(running) => self.running = Some(running),
+                    Err(e) => {
+                        e.to_string().report_err(state);
+                        return Processing::Completed
+                    }
+                }
+                self.generation = state.exec_generation();
+                *self.output_id.insert(state.add_command_output_message(&self.command_line))
+            }
+        };
+
+        // Safe unwrap, the command was started in the first process
+        let running = self.running.as_mut().unwrap();
+        if state.exec_generation() != self.generation {
+            running.kill();
+            return self.finish(state, node, String::from("cancelled"))
+        }
+
+        for _ in 0..Self::MAX_LINES_PER_PROCESS {
+            match running.output.try_recv() {
+                Ok((content, error)) => state.command_output_push(output_id, content, error),
+                Err(TryRecvError::Empty) => return Processing::Partial(Self::POLL_DELAY),
+                // The output is finished, the command is finished or about to finish
+                Err(TryRecvError::Disconnected) => return self.wait_exit(state, node),
+            }
+        }
+
+        // There could be more lines waiting, they are read without delay
+        Processing::Partial(Duration::ZERO)
+    }
+}
+
+pub struct StopExecCommand;
+
+impl Command for StopExecCommand {
+    fn name(&self) -> &'static str {
+        "stopexec"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop the running commands of ?exec"
+    }
+
+    fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
+        Ok(Box::new(StopExec {}))
+    }
+
+    fn local(&self, _param_str: &str) -> bool {
+        true
+    }
+}
+
+struct StopExec {}
+
+impl Action for StopExec {
+    fn process(&mut self, state: &mut State, _node: &ChatNode) -> Processing {
+        state.cancel_execs();
+        Processing::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{MessageType};
+    use crate::node::{NodeConfig};
+
+    fn run(exec: &mut Exec, state: &mut State, node: &ChatNode) {
+        while let Processing::Partial(delay) = exec.process(state, node) {
+            std::thread::sleep(delay);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exec_output_longer_than_a_batch() {
+        let node = ChatNode::new(NodeConfig::default()).unwrap();
+        let mut state = State::default();
+        let mut exec = Exec::new("seq 1 300", false);
+        run(&mut exec, &mut state, &node);
+
+        match &state.messages()[0].message_type {
+            MessageType::CommandOutput(output) => {
+                let lines = output.lines.iter().map(|line| line.content.as_str());
+                let expected = (1..=300).map(|number| number.to_string()).collect::<Vec<_>>();
+                assert_eq!(lines.collect::<Vec<_>>(), expected);
+                assert_eq!(output.exit.as_deref(), Some("exit code 0"));
+            }
+            _ => panic!("Must be a CommandOutput MessageType"),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stop_exec_kills_background_processes() {
+        let node = ChatNode::new(NodeConfig::default()).unwrap();
+        let mut state = State::default();
+        let mut exec = Exec::new("sleep 30 & echo started", false);
+        while state.messages().first().is_none_or(|message| message.text().lines().count() < 2) {
+            assert!(matches!(exec.process(&mut state, &node), Processing::Partial(_)));
+            std::thread::sleep(Exec::POLL_DELAY);
+        }
+
+        state.cancel_execs();
+        assert!(matches!(exec.process(&mut state, &node), Processing::Completed));
+        // The output is closed once the background sleep is killed
+        let output = exec.running.unwrap().output.recv_timeout(Duration::from_secs(5));
+        assert_eq!(output, Err(mpsc::RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn share_flag_followed_by_the_command() {
+        assert_eq!(ExecCommand::split_share_flag(" --share  ls -l"), (true, "ls -l"));
+        assert_eq!(ExecCommand::split_share_flag("--sharefoo ls"), (false, "--sharefoo ls"));
+        assert_eq!(ExecCommand::split_share_flag("ls --share"), (false, "ls --share"));
+        assert!(!ExecCommand.local("--share ls"));
+        assert!(ExecCommand.local("--sharefoo ls"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn parsing_does_not_run_the_command() {
+        let node = ChatNode::new(NodeConfig::default()).unwrap();
+        let mut state = State::default();
+        let file_path = std::env::temp_dir().join(format!("termchat-exec-{}", std::process::id()));
+        let command_line = format!("touch {}", file_path.display());
+        let mut exec = ExecCommand.parse_params(vec![command_line]).unwrap();
+        std::thread::sleep(Exec::POLL_DELAY);
+        assert!(!file_path.exists());
+
+        while let Processing::Partial(delay) = exec.process(&mut state, &node) {
+            std::thread::sleep(delay);
+        }
+        assert!(file_path.exists());
+        std::fs::remove_file(&file_path).unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+use crate::action::{Action, Processing};
+use crate::state::{State};
+use crate::util::{Reportable};
+
+use crate::node::{ChatNode};
+
+/// Shows the help of the commands as a system message
+pub struct ShowHelp {
+    text: String,
+}
+
+impl ShowHelp {
+    pub fn new(text: String) -> ShowHelp {
+        ShowHelp { text }
+    }
+}
+
+impl Action for ShowHelp {
+    fn process(&mut self, state: &mut State, _node: &ChatNode) -> Processing {
+        std::mem::take(&mut self.text).report_info(state);
+        Processing::Completed
+    }
+}
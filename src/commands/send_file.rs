@@ -1,15 +1,11 @@
 use crate::action::{Action, Processing};
-use crate::commands::{Command};
+use crate::commands::{Command, Param};
 use crate::state::{State};
-use crate::message::{NetMessage, Chunk};
 use crate::util::{Result, Reportable};
-use crate::encoder::{Encoder};
 
-use message_io::network::{NetworkController};
+use crate::node::{ChatNode};
 
-use std::path::{Path};
-use std::io::{Read};
-use std::time::{Duration};
+use std::path::{PathBuf};
 
 pub struct SendFileCommand;
 
@@ -18,73 +14,51 @@ impl Command for SendFileCommand {
         "send"
     }
 
+    fn description(&self) -> &'static str {
+        "Send a file to all the users in the room"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![Param::required("file", "Path of the file, it can use ~ and environment variables")]
+    }
+
     fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>> {
-        let param = params.first().ok_or("No file specified")?;
+        let param = params.first().ok_or_else(|| format!("Usage: {}", self.usage()))?;
         let file_path = shellexpand::full(param)?;
         match SendFile::new(&file_path) {
             Ok(action) => Ok(Box::new(action)),
             Err(e) => Err(e),
         }
     }
+
+    fn path_params(&self) -> bool {
+        true
+    }
 }
 
 pub struct SendFile {
-    file: std::fs::File,
-    file_name: String,
-    file_size: u64,
-    progress_id: Option<usize>,
-    encoder: Encoder,
+    file_path: PathBuf,
 }
 
 impl SendFile {
-    const CHUNK_SIZE: usize = 32768;
-
     pub fn new(file_path: &str) -> Result<SendFile> {
-        const READ_FILENAME_ERROR: &str = "Unable to read file name";
-        let file_path = Path::new(file_path);
-        let file_name = file_path
-            .file_name()
-            .ok_or(READ_FILENAME_ERROR)?
-            .to_str()
-            .ok_or(READ_FILENAME_ERROR)?
-            .to_string();
-
-        let file_size = std::fs::metadata(file_path)?.len();
-        let file = std::fs::File::open(file_path)?;
-
-        Ok(SendFile { file, file_name, file_size, progress_id: None, encoder: Encoder::new() })
+        let file_path = PathBuf::from(file_path);
+        if !file_path.is_file() {
+            return Err(format!("'{}' is not a file", file_path.display()).into())
+        }
+        Ok(SendFile { file_path })
     }
 }
 
 impl Action for SendFile {
-    fn process(&mut self, state: &mut State, network: &NetworkController) -> Processing {
-        if self.progress_id.is_none() {
-            let id = state.add_progress_message(&self.file_name, self.file_size);
-            self.progress_id = Some(id);
-        }
-
-        let mut data = [0; Self::CHUNK_SIZE];
-        let (bytes_read, chunk, processing) = match self.file.read(&mut data) {
-            Ok(0) => (0, Chunk::End, Processing::Completed),
-            Ok(bytes_read) => {
-                // We add a minor delay to introduce a rate in the sending.
-                let processing = Processing::Partial(Duration::from_micros(100));
-                (bytes_read, Chunk::Data(data[..bytes_read].to_vec()), processing)
+    fn process(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        // The file is sent by the node, its progress is notified by its events
+        match node.send_file(&self.file_path) {
+            Ok(transfer) => {
+                state.add_file_transfer(transfer.id, &transfer.file_name, transfer.size)
             }
-            Err(error) => {
-                format!("Error sending file. error: {}", error).report_err(state);
-                (0, Chunk::Error, Processing::Completed)
-            }
-        };
-
-        state.progress_message_update(self.progress_id.unwrap(), bytes_read as u64);
-
-        let net_message = NetMessage::UserData(self.file_name.clone(), chunk);
-        let message = self.encoder.encode(net_message);
-        for endpoint in state.all_user_endpoints() {
-            network.send(*endpoint, message);
+            Err(e) => format!("Error sending file. error: {}", e).report_err(state),
         }
-
-        processing
+        Processing::Completed
     }
 }
@@ -3,9 +3,8 @@ use crate::commands::{Command};
 use crate::state::{State};
 use crate::message::{NetMessage};
 use crate::util::{Result, Reportable};
-use crate::encoder::{Encoder};
 
-use message_io::network::{NetworkController, Endpoint};
+use crate::node::{ChatNode};
 use resize::px::RGB;
 use rgb::RGB8;
 use v4l::prelude::*;
@@ -24,6 +23,10 @@ impl Command for SendStreamCommand {
         "startstream"
     }
 
+    fn description(&self) -> &'static str {
+        "Start sending the video of the camera to all the users in the room"
+    }
+
     fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
         match SendStream::new() {
             Ok(action) => Ok(Box::new(action)),
@@ -35,7 +38,6 @@ pub struct SendStream {
     stream: MmapStream<'static>,
     width: usize,
     height: usize,
-    encoder: Encoder,
 }
 
 impl SendStream {
@@ -49,23 +51,26 @@ impl SendStream {
 
         let stream = MmapStream::with_buffers(&dev, Type::VideoCapture, 4)?;
 
-        Ok(SendStream { stream, width, height, encoder: Encoder::new() })
+        Ok(SendStream { stream, width, height })
     }
 }
 
 impl Action for SendStream {
-    fn process(&mut self, state: &mut State, network: &NetworkController) -> Processing {
+    fn process(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        state.sending_stream = true;
         if state.stop_stream {
             // stop stream and restore stop_stream to false for the next stream usage
             state.stop_stream = false;
-            self.send_all(network, state.all_user_endpoints(), NetMessage::Stream(None));
+            state.sending_stream = false;
+            node.send_all(NetMessage::Stream(None));
             return Processing::Completed
         }
         let (data, _metadata) = match self.stream.next() {
             Ok(d) => d,
             Err(e) => {
                 e.to_string().report_err(state);
-                self.send_all(network, state.all_user_endpoints(), NetMessage::Stream(None));
+                state.sending_stream = false;
+                node.send_all(NetMessage::Stream(None));
                 return Processing::Completed
             }
         };
@@ -91,26 +96,12 @@ impl Action for SendStream {
         });
 
         let message = NetMessage::Stream(Some((data, self.width, self.height)));
-        self.send_all(network, state.all_user_endpoints(), message);
+        node.send_all(message);
 
         Processing::Partial(Duration::from_millis(16)) //~60fps - delay of computation
     }
 }
 
-impl SendStream {
-    fn send_all<'a>(
-        &mut self,
-        network: &NetworkController,
-        endpoints: impl Iterator<Item = &'a Endpoint>,
-        net_message: NetMessage,
-    ) {
-        let message = self.encoder.encode(net_message);
-        for endpoint in endpoints {
-            network.send(*endpoint, message);
-        }
-    }
-}
-
 // Stop stream logic
 
 pub struct StopStreamCommand;
@@ -120,13 +111,17 @@ impl Command for StopStreamCommand {
         "stopstream"
     }
 
+    fn description(&self) -> &'static str {
+        "Stop sending the video of the camera"
+    }
+
     fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
         Ok(Box::new(StopStream {}))
     }
 }
 struct StopStream {}
 impl Action for StopStream {
-    fn process(&mut self, state: &mut State, _network: &NetworkController) -> Processing {
+    fn process(&mut self, state: &mut State, _node: &ChatNode) -> Processing {
         state.stop_stream = true;
         Processing::Completed
     }
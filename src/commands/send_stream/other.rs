@@ -9,6 +9,10 @@ impl Command for SendStreamCommand {
         "stream"
     }
 
+    fn description(&self) -> &'static str {
+        "Send the video of the camera, only supported on linux"
+    }
+
     fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
         Err(format!("{} command is not supported on this platform.", self.name()).into())
     }
@@ -19,6 +23,10 @@ impl Command for StopStreamCommand {
         "stopstream"
     }
 
+    fn description(&self) -> &'static str {
+        "Stop sending the video of the camera, only supported on linux"
+    }
+
     fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
         Err(format!("{} command is not supported on this platform.", self.name()).into())
     }
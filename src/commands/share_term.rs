@@ -0,0 +1,226 @@
+use crate::action::{Action, Processing};
+use crate::commands::{Command, Param};
+use crate::state::{State, SharedTerminal};
+use crate::message::{NetMessage, TermScreen};
+use crate::util::{Result, Reportable};
+
+use crate::node::{ChatNode};
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize};
+
+use std::io::{Read};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+pub struct ShareTermCommand;
+
+impl Command for ShareTermCommand {
+    fn name(&self) -> &'static str {
+        "share-term"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a command in a terminal whose screen is shown to all the users in the room"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![Param::required("command", "Command line run by the shell").multiple()]
+    }
+
+    fn raw_params(&self) -> bool {
+        true
+    }
+
+    fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>> {
+        let command_line = params.first().ok_or_else(|| format!("Usage: {}", self.usage()))?;
+        Ok(Box::new(ShareTerm::new(command_line)))
+    }
+}
+
+pub struct ShareTerm {
+    command_line: String,
+    // Started by the first process, not while the command is parsed
+    running: Option<RunningTerminal>,
+    // The screen as the other users have it, to send only the changes
+    last_screen: Option<vt100::Screen>,
+    last_contents: Instant,
+}
+
+struct RunningTerminal {
+    child: Box<dyn Child + Send + Sync>,
+    // Kept to not close the terminal while the command runs
+    _master: Box<dyn MasterPty + Send>,
+    output: Receiver<Vec<u8>>,
+}
+
+impl ShareTerm {
+    const COLS: u16 = 80;
+    // The whole screen is sent from time to time for the users connected later
+    const CONTENTS_PERIOD: Duration = Duration::from_secs(2);
+    const ROWS: u16 = 24;
+    const UPDATE_DELAY: Duration = Duration::from_millis(50);
+
+    pub fn new(command_line: &str) -> ShareTerm {
+        ShareTerm {
+            command_line: command_line.into(),
+            running: None,
+            last_screen: None,
+            last_contents: Instant::now(),
+        }
+    }
+
+    fn spawn(command_line: &str) -> Result<RunningTerminal> {
+        let size = PtySize { rows: Self::ROWS, cols: Self::COLS, pixel_width: 0, pixel_height: 0 };
+        let pair = portable_pty::native_pty_system().openpty(size)?;
+
+        #[cfg(not(windows))]
+        let mut command = CommandBuilder::new("sh");
+        #[cfg(not(windows))]
+        command.arg("-c");
+        #[cfg(windows)]
+        let mut command = CommandBuilder::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+        command.arg(command_line);
+        command.cwd(std::env::current_dir()?);
+        command.env("TERM", "xterm-256color");
+
+        let child = pair
+            .slave
+            .spawn_command(command)
+            .map_err(|e| format!("Unable to run '{}': {}", command_line, e))?;
+        let mut reader = pair.master.try_clone_reader()?;
+
+        let (sender, output) = mpsc::channel();
+        std::thread::Builder::new().name("termchat: shared terminal reader".into()).spawn(
+            move || {
+                let mut data = [0; 4096];
+                while let Ok(size) = reader.read(&mut data) {
+                    if size == 0 || sender.send(data[..size].to_vec()).is_err() {
+                        break
+                    }
+                }
+            },
+        )?;
+
+        Ok(RunningTerminal { child, _master: pair.master, output })
+    }
+
+    fn end(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        self.kill();
+        state.own_terminal = None;
+        node.send_all(NetMessage::TermScreen(TermScreen::End));
+        format!("The terminal running '{}' is no longer shared", self.command_line)
+            .report_info(state);
+        Processing::Completed
+    }
+
+    fn kill(&mut self) {
+        if let Some(running) = &mut self.running {
+            let _ = running.child.kill();
+        }
+    }
+}
+
+impl Action for ShareTerm {
+    fn process(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        if self.running.is_none() {
+            if state.own_terminal.is_some() {
+                String::from("A terminal is already shared, stop it first with ?stop-share-term")
+                    .report_err(state);
+                return Processing::Completed
+            }
+            match Self::spawn(&self.command_line) {
+                Ok(running) => self.running = Some(running),
+                Err(e) => {
+                    e.to_string().report_err(state);
+                    return Processing::Completed
+                }
+            }
+            state.stop_term_share = false;
+            state.own_terminal =
+                Some(SharedTerminal::new(&self.command_line, Self::ROWS, Self::COLS));
+        }
+
+        if state.stop_term_share {
+            state.stop_term_share = false;
+            return self.end(state, node)
+        }
+
+        // Safe unwraps, both are set by the first process and removed only when it ends
+        let terminal = state.own_terminal.as_mut().unwrap();
+        let output = &self.running.as_ref().unwrap().output;
+        let mut finished = false;
+        loop {
+            match output.try_recv() {
+                Ok(data) => terminal.parser.process(&data),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true;
+                    break
+                }
+            }
+        }
+
+        let screen = terminal.parser.screen().clone();
+        let message = match &self.last_screen {
+            Some(last_screen) if self.last_contents.elapsed() < Self::CONTENTS_PERIOD => {
+                let diff = screen.contents_diff(last_screen);
+                Some(diff).filter(|diff| !diff.is_empty()).map(TermScreen::Diff)
+            }
+            _ => {
+                self.last_contents = Instant::now();
+                let contents = screen.contents_formatted();
+                Some(TermScreen::Contents(
+                    self.command_line.clone(),
+                    Self::ROWS,
+                    Self::COLS,
+                    contents,
+                ))
+            }
+        };
+        if let Some(message) = message {
+            node.send_all(NetMessage::TermScreen(message));
+            self.last_screen = Some(screen);
+        }
+
+        match finished {
+            true => self.end(state, node),
+            false => Processing::Partial(Self::UPDATE_DELAY),
+        }
+    }
+}
+
+impl Drop for ShareTerm {
+    fn drop(&mut self) {
+        // The command could run forever, it must not survive to termchat
+        self.kill();
+    }
+}
+
+pub struct StopShareTermCommand;
+
+impl Command for StopShareTermCommand {
+    fn name(&self) -> &'static str {
+        "stop-share-term"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop sharing the terminal of ?share-term"
+    }
+
+    fn parse_params(&self, _params: Vec<String>) -> Result<Box<dyn Action>> {
+        Ok(Box::new(StopShareTerm {}))
+    }
+}
+
+struct StopShareTerm {}
+
+impl Action for StopShareTerm {
+    fn process(&mut self, state: &mut State, _node: &ChatNode) -> Processing {
+        match state.own_terminal.is_some() {
+            true => state.stop_term_share = true,
+            false => String::from("There is no shared terminal").report_err(state),
+        }
+        Processing::Completed
+    }
+}
@@ -0,0 +1,255 @@
+use crate::action::{Action, Processing};
+use crate::commands::{Command, Param};
+use crate::state::{State, ChatMessage, MessageType, TailLines};
+use crate::message::{NetMessage};
+use crate::util::{Result, Reportable};
+
+use crate::node::{ChatNode};
+
+use std::fs::{File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration};
+
+pub struct TailCommand;
+
+impl TailCommand {
+    const FILTER_FLAG: &'static str = "--filter=";
+}
+
+impl Command for TailCommand {
+    fn name(&self) -> &'static str {
+        "tail"
+    }
+
+    fn description(&self) -> &'static str {
+        "Follow a file and send the lines appended to it to all the users in the room"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param::optional("--filter=<text>", "Send only the lines containing the text"),
+            Param::required("file", "Path of the file, it can use ~ and environment variables"),
+        ]
+    }
+
+    fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>> {
+        let usage = || format!("Usage: {}", self.usage());
+        let (mut filter, mut file_path) = (None, None);
+        for param in params {
+            match param.strip_prefix(Self::FILTER_FLAG) {
+                Some(text) => filter = Some(text.to_string()),
+                None if file_path.is_none() => file_path = Some(param),
+                None => return Err(usage().into()),
+            }
+        }
+        let file_path = shellexpand::full(&file_path.ok_or_else(usage)?)?.into_owned();
+        match Tail::new(&file_path, filter) {
+            Ok(action) => Ok(Box::new(action)),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn path_params(&self) -> bool {
+        true
+    }
+}
+
+pub struct Tail {
+    file: File,
+    // Canonical, the same file can be followed only once
+    file_path: PathBuf,
+    file_name: String,
+    filter: Option<String>,
+    // Position until the file was read
+    position: u64,
+    // Read bytes of a line not finished yet
+    partial_line: Vec<u8>,
+    // Given once it is followed, a later `?tail` of the same file has other id
+    id: Option<usize>,
+}
+
+impl Tail {
+    // Lines sent each poll at most, the older ones are skipped when more lines are appended
+    const MAX_LINES_PER_POLL: usize = 10;
+    const MAX_READ_PER_POLL: u64 = 1 << 20;
+    const POLL_DELAY: Duration = Duration::from_millis(500);
+
+    pub fn new(file_path: &str, filter: Option<String>) -> Result<Tail> {
+        const READ_FILENAME_ERROR: &str = "Unable to read file name";
+        let file_path = Path::new(file_path);
+        let file_name = file_path
+            .file_name()
+            .ok_or(READ_FILENAME_ERROR)?
+            .to_str()
+            .ok_or(READ_FILENAME_ERROR)?
+            .to_string();
+
+        let unable = |e| format!("Unable to follow '{}': {}", file_path.display(), e);
+        let file = File::open(file_path).map_err(unable)?;
+        let file_path = std::fs::canonicalize(file_path).map_err(unable)?;
+        // Only the lines appended from now are sent
+        let position = file.metadata()?.len();
+
+        Ok(Tail {
+            file,
+            file_path,
+            file_name,
+            filter,
+            position,
+            partial_line: Vec::new(),
+            id: None,
+        })
+    }
+
+    /// The complete lines appended since the last read
+    fn read_lines(&mut self) -> Result<Vec<String>> {
+        let len = self.file.metadata()?.len();
+        if len < self.position {
+            // The file was truncated, it is followed again from its start
+            self.position = 0;
+            self.partial_line.clear();
+        }
+
+        let mut data = Vec::new();
+        self.file.seek(SeekFrom::Start(self.position))?;
+        (&mut self.file).take(Self::MAX_READ_PER_POLL).read_to_end(&mut data)?;
+        self.position += data.len() as u64;
+        self.partial_line.extend(data);
+
+        let end = match self.partial_line.iter().rposition(|byte| *byte == b'\n') {
+            Some(index) => index + 1,
+            None => return Ok(Vec::new()),
+        };
+        let data = self.partial_line.drain(..end).collect::<Vec<_>>();
+        let lines = String::from_utf8_lossy(&data).lines().map(String::from).collect();
+        Ok(lines)
+    }
+}
+
+impl Action for Tail {
+    fn process(&mut self, state: &mut State, node: &ChatNode) -> Processing {
+        let id = match self.id {
+            Some(id) => id,
+            None => match state.start_tail(&self.file_path) {
+                Some(id) => {
+                    format!("Following '{}', stop it with ?untail", self.file_path.display())
+                        .report_info(state);
+                    *self.id.insert(id)
+                }
+                None => {
+                    format!("'{}' is already followed", self.file_path.display()).report_err(state);
+                    return Processing::Completed
+                }
+            },
+        };
+
+        if !state.is_tailed(id) {
+            return Processing::Completed
+        }
+
+        let mut lines = match self.read_lines() {
+            Ok(lines) => lines,
+            Err(e) => {
+                state.stop_tail(id);
+                format!("Stopped following '{}': {}", self.file_name, e).report_err(state);
+                return Processing::Completed
+            }
+        };
+        if let Some(filter) = &self.filter {
+            lines.retain(|line| line.contains(filter.as_str()));
+        }
+
+        if !lines.is_empty() {
+            let skipped_lines = lines.len().saturating_sub(Self::MAX_LINES_PER_POLL);
+            let lines = lines.split_off(skipped_lines);
+
+            let message =
+                NetMessage::TailLines(self.file_name.clone(), lines.clone(), skipped_lines);
+            node.send_all(message);
+
+            let user = state.connection().map(|connection| connection.user_name.clone());
+            let tail = TailLines { file_name: self.file_name.clone(), lines, skipped_lines };
+            state.add_message(ChatMessage::new(
+                format!("{} (me)", user.unwrap_or_default()),
+                MessageType::TailLines(tail),
+            ));
+        }
+
+        Processing::Partial(Self::POLL_DELAY)
+    }
+}
+
+pub struct UntailCommand;
+
+impl Command for UntailCommand {
+    fn name(&self) -> &'static str {
+        "untail"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop following a file of ?tail, or all of them"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![Param::optional("file", "Name or path of the followed file")]
+    }
+
+    fn parse_params(&self, params: Vec<String>) -> Result<Box<dyn Action>> {
+        Ok(Box::new(Untail { file_name: params.into_iter().next() }))
+    }
+}
+
+struct Untail {
+    file_name: Option<String>,
+}
+
+impl Action for Untail {
+    fn process(&mut self, state: &mut State, _node: &ChatNode) -> Processing {
+        let stopped = state.stop_tails(self.file_name.as_deref());
+        match (stopped.is_empty(), &self.file_name) {
+            (true, Some(file_name)) => format!("'{}' is not followed", file_name).report_err(state),
+            (true, None) => String::from("There are no followed files").report_err(state),
+            (false, _) => {
+                for file_path in stopped {
+                    format!("Stopped following '{}'", file_path.display()).report_info(state);
+                }
+            }
+        }
+        Processing::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{NodeConfig};
+
+    #[test]
+    fn tail_again_after_untail() {
+        let dir = std::env::temp_dir().join(format!("termchat-tail-{}", std::process::id()));
+        let (a_log, b_log) = (dir.join("a").join("log"), dir.join("b").join("log"));
+        for log in [&a_log, &b_log] {
+            std::fs::create_dir_all(log.parent().unwrap()).unwrap();
+            std::fs::write(log, "").unwrap();
+        }
+        let node = ChatNode::new(NodeConfig::default()).unwrap();
+        let mut state = State::default();
+
+        // Files with the same name in different directories
+        let mut a_tail = Tail::new(a_log.to_str().unwrap(), None).unwrap();
+        let mut b_tail = Tail::new(b_log.to_str().unwrap(), None).unwrap();
+        assert!(matches!(a_tail.process(&mut state, &node), Processing::Partial(_)));
+        assert!(matches!(b_tail.process(&mut state, &node), Processing::Partial(_)));
+
+        // Followed again before the previous tail polls
+        assert_eq!(state.stop_tails(Some(a_log.to_str().unwrap())).len(), 1);
+        let mut new_a_tail = Tail::new(a_log.to_str().unwrap(), None).unwrap();
+        assert!(matches!(new_a_tail.process(&mut state, &node), Processing::Partial(_)));
+        assert!(matches!(a_tail.process(&mut state, &node), Processing::Completed));
+        assert!(matches!(new_a_tail.process(&mut state, &node), Processing::Partial(_)));
+
+        assert_eq!(state.stop_tails(Some("log")).len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
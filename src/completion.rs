@@ -0,0 +1,165 @@
+use crate::commands::{CommandManager};
+
+use std::path::{MAIN_SEPARATOR};
+
+/// Candidates to complete the word that ends at the cursor.
+/// Returns the position where that word starts and the sorted candidates,
+/// or None if the word can not be completed.
+pub fn complete<'a>(
+    input: &[char],
+    commands: &CommandManager,
+    user_names: impl Iterator<Item = &'a String>,
+) -> Option<(usize, Vec<String>)> {
+    let start = word_start(input);
+    let word = input[start..].iter().collect::<String>();
+
+    let mut candidates = if start == 0 && word.starts_with(CommandManager::COMMAND_PREFIX) {
+        let name = &word[CommandManager::COMMAND_PREFIX.len()..];
+        commands
+            .command_names()
+            .filter(|command| command.starts_with(name))
+            .map(|command| format!("{}{} ", CommandManager::COMMAND_PREFIX, command))
+            .collect()
+    }
+    else if let Some(name) = word.strip_prefix('@') {
+        user_names
+            .filter(|user| user.starts_with(name))
+            .map(|user| format!("@{} ", user))
+            .collect::<Vec<_>>()
+    }
+    else if start > 0 && commands.expects_paths(&command_name(input)) {
+        path_candidates(&word)
+    }
+    else {
+        return None
+    };
+
+    candidates.sort();
+    candidates.dedup();
+    match candidates.is_empty() {
+        true => None,
+        false => Some((start, candidates)),
+    }
+}
+
+/// Words are separated by whitespaces not escaped by a backslash
+fn word_start(input: &[char]) -> usize {
+    (0..input.len())
+        .rev()
+        .find(|&index| input[index].is_whitespace() && (index == 0 || input[index - 1] != '\\'))
+        .map(|index| index + 1)
+        .unwrap_or(0)
+}
+
+fn command_name(input: &[char]) -> String {
+    input
+        .iter()
+        .take_while(|character| !character.is_whitespace())
+        .collect::<String>()
+        .trim_start_matches(CommandManager::COMMAND_PREFIX)
+        .into()
+}
+
+/// The directory part of the word is kept as the user wrote it,
+/// and it is expanded with `shellexpand` only to read its entries.
+fn path_candidates(word: &str) -> Vec<String> {
+    let (dir, file_prefix) = match word.rfind(['/', MAIN_SEPARATOR]) {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word),
+    };
+
+    let dir_path = match shellexpand::full(&unescape(dir)) {
+        Ok(dir_path) if dir_path.is_empty() => ".".to_string(),
+        Ok(dir_path) => dir_path.into_owned(),
+        Err(_) => return Vec::new(),
+    };
+    let file_prefix = unescape(file_prefix);
+
+    let entries = match std::fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let hidden = file_name.starts_with('.') && !file_prefix.starts_with('.');
+            if hidden || !file_name.starts_with(&file_prefix) {
+                return None
+            }
+            let is_dir = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+            let ending = if is_dir { "/" } else { " " };
+            Some(format!("{}{}{}", dir, shellwords::escape(&file_name), ending))
+        })
+        .collect()
+}
+
+fn unescape(fragment: &str) -> String {
+    match shellwords::split(fragment) {
+        Ok(mut words) if words.len() == 1 => words.remove(0),
+        _ => fragment.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::send_file::{SendFileCommand};
+
+    fn chars(input: &str) -> Vec<char> {
+        input.chars().collect()
+    }
+
+    fn commands() -> CommandManager {
+        CommandManager::default().with(SendFileCommand)
+    }
+
+    #[test]
+    fn command_candidates() {
+        let users = Vec::<String>::new();
+        let (start, candidates) = complete(&chars("?s"), &commands(), users.iter()).unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(candidates, vec!["?send "]);
+        assert!(complete(&chars("?x"), &commands(), users.iter()).is_none());
+        // Only the first word is a command
+        assert!(complete(&chars("hi ?s"), &commands(), users.iter()).is_none());
+    }
+
+    #[test]
+    fn user_candidates() {
+        let users = [String::from("bob"), String::from("alice"), String::from("bill")];
+        let (start, candidates) = complete(&chars("hi @b"), &commands(), users.iter()).unwrap();
+        assert_eq!(start, 3);
+        assert_eq!(candidates, vec!["@bill ", "@bob "]);
+    }
+
+    #[test]
+    fn path_candidates_of_path_commands() {
+        let dir = std::env::temp_dir().join(format!("termchat-completion-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("some dir")).unwrap();
+        std::fs::write(dir.join("some file"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+
+        let users = Vec::<String>::new();
+        let input = format!("?send {}/so", dir.display());
+        let (start, candidates) = complete(&chars(&input), &commands(), users.iter()).unwrap();
+        assert_eq!(start, "?send ".len());
+        let expected = [
+            format!("{}/some\\ dir/", dir.display()),
+            format!("{}/some\\ file ", dir.display()),
+        ];
+        assert_eq!(candidates, expected);
+
+        // Escaped whitespaces are part of the word
+        let input = format!("?send {}/some\\ f", dir.display());
+        let (start, candidates) = complete(&chars(&input), &commands(), users.iter()).unwrap();
+        assert_eq!(start, "?send ".len());
+        assert_eq!(candidates, vec![format!("{}/some\\ file ", dir.display())]);
+
+        // The params of other commands are not paths
+        let input = format!("?other {}/so", dir.display());
+        assert!(complete(&chars(&input), &commands(), users.iter()).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
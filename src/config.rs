@@ -1,96 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
 use std::net::{SocketAddrV4};
+use std::path::{Path, PathBuf};
 use clap::ArgMatches;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
 use crate::util::Result;
+use crate::keybindings::{KeyBindings};
+use crate::node::{NodeConfig};
 use tui::style::Color;
+use toml::{Value, value::Table};
 
+/// Fields not specified in the config file take their default value
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub discovery_addr: SocketAddrV4,
     pub tcp_server_port: u16,
     pub user_name: String,
     pub terminal_bell: bool,
+    pub editing_mode: EditingMode,
+    pub mouse: bool,
+    /// Unix socket where other tools can send messages and run commands
+    pub control_socket: bool,
+    /// A theme table, or the name of a theme
+    #[serde(deserialize_with = "deserialize_theme")]
     pub theme: Theme,
+    pub layout: PanelLayout,
+    pub keys: KeyBindings,
+    /// Other names for the commands, as `s = "send"`
+    pub aliases: BTreeMap<String, String>,
+    pub macros: BTreeMap<String, Macro>,
+    /// Values given by the command line, they are kept to be applied again on reloads
+    #[serde(skip)]
+    pub overrides: Overrides,
+    /// File the config was read from, None if it was not read from a file
+    #[serde(skip)]
+    pub file_path: Option<PathBuf>,
+    /// Problems found while loading the config, to be reported once the application runs
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// `vi` enables a modal editing of the input, with normal and insert modes
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EditingMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+/// Sizes and positions of the panels
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelLayout {
+    /// Minimum rows of the input panel, it grows with the written lines up to half of the screen
+    pub input_height: u16,
+    /// Columns of the video panel when it is placed at the right
+    pub video_panel_width: u16,
+    /// Rows of the video panel when it is placed at the bottom
+    pub video_panel_height: u16,
+    pub video_panel: VideoPanelPosition,
+    /// Panels without borders, to fit in small terminals
+    pub compact: bool,
+    /// One line at the bottom with the connection details
+    pub status_bar: bool,
+}
+
+impl Default for PanelLayout {
+    fn default() -> Self {
+        PanelLayout {
+            input_height: 6,
+            video_panel_width: 30,
+            video_panel_height: 10,
+            video_panel: VideoPanelPosition::default(),
+            compact: false,
+            status_bar: true,
+        }
+    }
+}
+
+/// Where the video panel is shown while receiving streams
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoPanelPosition {
+    #[default]
+    Right,
+    Bottom,
+    Hidden,
+}
+
+/// Text or commands written by a command defined by the user,
+/// with `$1`, `$2`... replaced by the params of the command and `$*` by all of them
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Macro {
+    Line(String),
+    Lines(Vec<String>),
+}
+
+impl Macro {
+    pub fn lines(&self) -> &[String] {
+        match self {
+            Macro::Line(line) => std::slice::from_ref(line),
+            Macro::Lines(lines) => lines,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Overrides {
+    config_path: Option<PathBuf>,
+    discovery_addr: Option<SocketAddrV4>,
+    tcp_server_port: Option<u16>,
+    user_name: Option<String>,
+    quiet_mode: bool,
+    theme: Option<String>,
+}
+
+impl Overrides {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        // the next unwrap are safe because we use clap validator
+        Overrides {
+            config_path: matches.value_of("config").map(PathBuf::from),
+            discovery_addr: matches.value_of("discovery").map(|addr| addr.parse().unwrap()),
+            tcp_server_port: matches.value_of("tcp_server_port").map(|port| port.parse().unwrap()),
+            user_name: matches.value_of("username").map(|user_name| user_name.into()),
+            quiet_mode: matches.is_present("quiet-mode"),
+            theme: matches.value_of("theme").map(|theme| theme.into()),
+        }
+    }
+
+    fn apply(&self, config: &mut Config) -> Result<()> {
+        if let Some(discovery_addr) = self.discovery_addr {
+            config.discovery_addr = discovery_addr;
+        }
+        if let Some(tcp_server_port) = self.tcp_server_port {
+            config.tcp_server_port = tcp_server_port;
+        }
+        if let Some(user_name) = &self.user_name {
+            config.user_name = user_name.clone();
+        }
+        if self.quiet_mode {
+            config.terminal_bell = false;
+        }
+        if let Some(theme) = &self.theme {
+            config.theme = Theme::load(theme)?;
+        }
+        config.overrides = self.clone();
+        Ok(())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
+        let node = NodeConfig::default();
         Config {
-            discovery_addr: "238.255.0.1:5877".parse().unwrap(),
-            tcp_server_port: "0".parse().unwrap(),
-            user_name: whoami::username(),
+            discovery_addr: node.discovery_addr,
+            tcp_server_port: node.tcp_server_port,
+            user_name: node.user_name,
             terminal_bell: true,
+            editing_mode: EditingMode::default(),
+            mouse: false,
+            control_socket: true,
             theme: Theme::default(),
+            layout: PanelLayout::default(),
+            keys: KeyBindings::default(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            overrides: Overrides::default(),
+            file_path: None,
+            warnings: Vec::new(),
         }
     }
 }
 
-impl Config {
-    /// Try to read config file from disk
-    /// If it does not exist, create it with default config values, and return that
-    /// If it fails for any other reason return None
-    fn from_config_file() -> Option<Self> {
-        let config_dir_path = dirs_next::config_dir()?.join("termchat");
-        if let Err(e) = std::fs::create_dir_all(&config_dir_path) {
-            if e.kind() != std::io::ErrorKind::AlreadyExists {
-                return None
-            }
+impl From<&Config> for NodeConfig {
+    fn from(config: &Config) -> Self {
+        NodeConfig {
+            user_name: config.user_name.clone(),
+            discovery_addr: config.discovery_addr,
+            tcp_server_port: config.tcp_server_port,
+            ..NodeConfig::default()
         }
-        let config_file_path = config_dir_path.join("config");
+    }
+}
 
-        let create_config = |config_file_path| -> Result<Config> {
-            let config = Config::default();
-            std::fs::write(config_file_path, toml::to_string(&config)?)?;
-            Ok(config)
-        };
+impl Config {
+    /// Location of the config file if no other is given: `$ConfigDir/termchat/config`
+    pub fn default_file_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("config"))
+    }
 
-        match std::fs::read_to_string(&config_file_path) {
-            Ok(config) => toml::from_str(&config).ok(),
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+    /// The config file given by `--config`, or the default one
+    fn resolve_file_path(config_path: Option<&Path>) -> Result<PathBuf> {
+        match config_path {
+            Some(config_path) => Ok(config_path.to_path_buf()),
+            None => Ok(Config::default_file_path().ok_or("No config directory found")?),
+        }
+    }
+
+    /// Try to read config file from disk, with the environment variables over it
+    /// If the default config file does not exist, create it with default config values
+    fn from_config_file(config_path: Option<&Path>) -> Result<Self> {
+        let config_file_path = Config::resolve_file_path(config_path)?;
+        let config = match std::fs::read_to_string(&config_file_path) {
+            Ok(content) => Config::from_toml(Some(&content), &env_variables())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && config_path.is_none() => {
                 // Config file was not found -> create it with default_values
-                match create_config(&config_file_path) {
-                    Ok(config) => Some(config),
-                    Err(_) => None,
-                }
+                // Safe unwrap, the file path is built from a directory
+                std::fs::create_dir_all(config_file_path.parent().unwrap())?;
+                std::fs::write(&config_file_path, Config::default_toml()?)?;
+                Config::from_toml(None, &env_variables())?
             }
-            Err(_) => None,
-        }
+            Err(e) => return Err(format!("'{}': {}", config_file_path.display(), e).into()),
+        };
+        Ok(Config { file_path: Some(config_file_path), ..config })
     }
 
-    /// Read configuration file from disk
-    /// If it fails for any reason use default Config value
-    /// If the user uses the cli arguments they will override the default values
-    pub fn from_matches(matches: ArgMatches) -> Self {
-        let mut config = Config::from_config_file().unwrap_or_default();
+    /// Parse the content of a config file, and the `TERMCHAT_*` environment variables over it.
+    /// An unknown or invalid key does not discard the whole config:
+    /// only that key keeps its previous value, and it is reported in the warnings.
+    /// The variables that are not config keys are ignored, they could be used by others.
+    fn from_toml(content: Option<&str>, variables: &[(String, String)]) -> Result<Self> {
+        use toml::Value;
+        let mut merged = match Value::try_from(Config::default())? {
+            Value::Table(table) => table,
+            _ => unreachable!("Config is serialized as a table"),
+        };
 
-        // the next unwrap are safe because we use clap validator
-        if let Some(discovery_addr) = matches.value_of("discovery") {
-            config.discovery_addr = discovery_addr.parse().unwrap();
-        }
-        if let Some(tcp_server_port) = matches.value_of("tcp_server_port") {
-            config.tcp_server_port = tcp_server_port.parse().unwrap();
+        let mut warnings = Vec::new();
+        if let Some(content) = content {
+            let mut problems = merge_layer(&mut merged, toml::from_str(content)?)
+                .into_iter()
+                .map(|(name, problem)| (key_line(content, &name), problem))
+                .collect::<Vec<_>>();
+            problems.sort();
+            warnings.extend(problems.into_iter().map(|(line, problem)| match line {
+                Some(line) => format!("Config line {}: {}", line, problem),
+                None => format!("Config: {}", problem),
+            }));
         }
-        if let Some(user_name) = matches.value_of("username") {
-            config.user_name = user_name.parse().unwrap();
+        for (variable, layer) in env_layers(&merged, variables) {
+            let problems = match layer {
+                Ok(layer) => merge_layer(&mut merged, layer),
+                Err(problem) => vec![(variable.clone(), problem)],
+            };
+            for (_, problem) in problems {
+                warnings.push(format!("Environment variable {}: {}", variable, problem));
+            }
         }
-        if matches.value_of("quiet-mode").is_some() {
-            config.terminal_bell = false;
+
+        let config = Value::Table(merged).try_into()?;
+        Ok(Config { warnings, ..config })
+    }
+
+    /// Problems found in the config file, it fails if the file can not be read or parsed.
+    /// The environment variables are not checked, they are not part of the file.
+    pub fn check_file(config_path: Option<&Path>) -> Result<Vec<String>> {
+        let config_file_path = Config::resolve_file_path(config_path)?;
+        let content = std::fs::read_to_string(&config_file_path)
+            .map_err(|e| format!("'{}': {}", config_file_path.display(), e))?;
+        let config = Config::from_toml(Some(&content), &[])?;
+
+        let mut problems = config.warnings.clone();
+        if let Err(e) = config.keys.key_map() {
+            problems.push(e.to_string());
         }
-        if let Some(theme) = matches.value_of("theme") {
-            if theme == "dark" {
-                config.theme = Theme::dark_theme();
+        Ok(problems)
+    }
+
+    /// The content of a config file with all the default values
+    pub fn default_toml() -> Result<String> {
+        Ok(toml::to_string(&Config::default())?)
+    }
+
+    /// Read configuration file from disk, given by `--config` or the default one
+    /// If it fails for any reason use default Config value, and keep the reason as a warning
+    /// The values are taken from, in order of precedence:
+    /// the cli arguments, the `TERMCHAT_*` environment variables, the file and the defaults.
+    /// Fails if the configured key bindings or the chosen theme are not valid
+    pub fn from_matches(matches: ArgMatches) -> Result<Self> {
+        let overrides = Overrides::from_matches(&matches);
+        let config_path = overrides.config_path.as_deref();
+        let mut config = Config::from_config_file(config_path).unwrap_or_else(|e| {
+            let mut config = Config::from_toml(None, &env_variables()).unwrap_or_default();
+            let warning = format!("Config file not loaded, using the default values: {}", e);
+            config.warnings.insert(0, warning);
+            // Still watched, the file can be fixed while running
+            config.file_path = Config::resolve_file_path(config_path).ok();
+            config
+        });
+        overrides.apply(&mut config)?;
+
+        config.keys.key_map()?;
+
+        Ok(config)
+    }
+
+    /// Aliases and macros in the form used by the commands
+    pub fn user_commands(&self) -> (HashMap<String, String>, HashMap<String, Vec<String>>) {
+        let aliases = self.aliases.clone().into_iter().collect();
+        let macros = self
+            .macros
+            .iter()
+            .map(|(name, lines)| (name.clone(), lines.lines().to_vec()))
+            .collect();
+        (aliases, macros)
+    }
+
+    /// Read again the config file, keeping the command line overrides.
+    /// Unlike at startup, any error reading the file is returned.
+    pub fn reload(&self) -> Result<Self> {
+        let file_path = self.file_path.as_deref().ok_or("The config was not read from a file")?;
+        let mut config = Config::from_config_file(Some(file_path))?;
+        self.overrides.apply(&mut config)?;
+
+        config.keys.key_map()?;
+
+        Ok(config)
+    }
+}
+
+/// Tables whose keys are not fixed fields but free names, as the key chords of `[keys]`
+const FREE_TABLES: &[&str] = &["keys", "aliases", "macros"];
+
+const ENV_PREFIX: &str = "TERMCHAT_";
+
+/// Merge the values of a layer over the config table, key by key.
+/// Returns the name and the problem of each unknown or invalid key, that are not merged.
+fn merge_layer(config: &mut Table, layer: Table) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    for (key, value) in layer {
+        match (config.get(&key).cloned(), value) {
+            (None, _) => problems.push((key.clone(), format!("unknown key '{}'", key))),
+            (Some(Value::Table(mut table)), Value::Table(fields)) => {
+                for (field, value) in fields {
+                    let name = format!("{}.{}", key, field);
+                    if !table.contains_key(&field) && !FREE_TABLES.contains(&key.as_str()) {
+                        problems.push((name.clone(), format!("unknown key '{}'", name)));
+                        continue
+                    }
+                    let previous = table.insert(field.clone(), value);
+                    if let Err(e) = merge_value(config, &key, Value::Table(table.clone())) {
+                        problems.push((name.clone(), format!("invalid '{}': {}", name, e)));
+                        match previous {
+                            Some(previous) => table.insert(field, previous),
+                            None => table.remove(&field),
+                        };
+                    }
+                }
             }
-            else {
-                config.theme = Theme::light_theme();
+            (Some(_), value) => {
+                if let Err(e) = merge_value(config, &key, value) {
+                    problems.push((key.clone(), format!("invalid '{}': {}", key, e)));
+                }
             }
         }
+    }
+    problems
+}
+
+/// Set a value in the config table if it is valid for its key.
+/// Only that key is deserialized to check it, the rest of the fields take their default values.
+fn merge_value(config: &mut Table, key: &str, value: Value) -> Result<()> {
+    let mut field = Table::new();
+    field.insert(key.into(), value.clone());
+    Value::Table(field).try_into::<Config>()?;
+    config.insert(key.into(), value);
+    Ok(())
+}
+
+/// The `TERMCHAT_*` environment variables, sorted by name
+fn env_variables() -> Vec<(String, String)> {
+    let mut variables = std::env::vars_os()
+        .filter_map(|(variable, value)| {
+            Some((variable.into_string().ok()?, value.into_string().ok()?))
+        })
+        .filter(|(variable, _)| variable.starts_with(ENV_PREFIX))
+        .collect::<Vec<_>>();
+    variables.sort();
+    variables
+}
 
-        config
+/// Layers given by the `TERMCHAT_<KEY>` and `TERMCHAT_<TABLE>__<KEY>` environment variables,
+/// as `TERMCHAT_USER_NAME` or `TERMCHAT_THEME__CHAT_PANEL_COLOR`.
+/// The names are lowercased, and in `[keys]` the `_` of the name are the `-` of the chord.
+/// The variables that don't name a config key are skipped,
+/// and the ones entering a key that is not a table, as a theme name, are a problem.
+fn env_layers(
+    config: &Table,
+    variables: &[(String, String)],
+) -> Vec<(String, std::result::Result<Table, String>)> {
+    variables
+        .iter()
+        .filter_map(|(variable, value)| {
+            let name = variable.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let mut path = name.splitn(2, "__");
+            let key = path.next()?.to_string();
+            let current = config.get(&key)?;
+            let layer_value = match path.next() {
+                Some(field) => {
+                    let field = match key.as_str() {
+                        "keys" => field.replace('_', "-"),
+                        _ => field.into(),
+                    };
+                    let current = match current.as_table() {
+                        Some(table) => table.get(&field),
+                        None => {
+                            let problem = format!("'{}' is not a table to set '{}'", key, field);
+                            return Some((variable.clone(), Err(problem)))
+                        }
+                    };
+                    if current.is_none() && !FREE_TABLES.contains(&key.as_str()) {
+                        return None
+                    }
+                    let mut table = Table::new();
+                    table.insert(field, env_value(value, current));
+                    Value::Table(table)
+                }
+                None => env_value(value, Some(current)),
+            };
+            let mut layer = Table::new();
+            layer.insert(key, layer_value);
+            Some((variable.clone(), Ok(layer)))
+        })
+        .collect()
+}
+
+/// The value of an environment variable is read as a toml value,
+/// unless it replaces a string or it is not a valid toml value
+fn env_value(value: &str, current: Option<&Value>) -> Value {
+    if let Some(Value::String(_)) = current {
+        return Value::String(value.into())
     }
+    toml::from_str::<Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.into()))
 }
 
+/// Line of the config file where a key is defined, as `user_name` or `theme.date_color`.
+/// The key can be in a `[table]`, written with dots as `theme.date_color = ...`,
+/// or in an inline table as `theme = { date_color = ... }`, then the line of the table is given.
+fn key_line(content: &str, name: &str) -> Option<usize> {
+    let mut current_table = String::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            current_table = dotted_key(header.trim_end_matches(']'));
+            continue
+        }
+        let key = match line.split_once('=') {
+            Some((key, _)) if !line.starts_with('#') => dotted_key(key),
+            _ => continue,
+        };
+        let key = match current_table.is_empty() {
+            true => key,
+            false => format!("{}.{}", current_table, key),
+        };
+        if key == name || name.strip_prefix(&key).is_some_and(|field| field.starts_with('.')) {
+            return Some(index + 1)
+        }
+    }
+    None
+}
+
+/// A toml key without the spaces and quotes around its parts, as `theme.date_color`
+fn dotted_key(key: &str) -> String {
+    key.split('.')
+        .map(|part| part.trim().trim_matches(|c| c == '"' || c == '\''))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn config_dir() -> Option<PathBuf> {
+    Some(dirs_next::config_dir()?.join("termchat"))
+}
+
+fn deserialize_theme<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Theme, D::Error> {
+    use serde::de::Error;
+    match Value::deserialize(deserializer)? {
+        Value::String(name) => Theme::load(&name).map_err(D::Error::custom),
+        value => value.try_into().map_err(D::Error::custom),
+    }
+}
+
+fn deserialize_message_colors<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<Color>, D::Error> {
+    use serde::de::Error;
+    let colors = Vec::<Color>::deserialize(deserializer)?;
+    match colors.is_empty() {
+        true => Err(D::Error::custom("at least one color is needed")),
+        false => Ok(colors),
+    }
+}
+
+/// Fields not specified in a theme take the value of the dark theme
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Theme {
+    /// Colors of the users, at least one
+    #[serde(deserialize_with = "deserialize_message_colors")]
     pub message_colors: Vec<Color>,
     pub my_user_color: Color,
     pub date_color: Color,
@@ -110,6 +523,58 @@ impl Default for Theme {
 }
 
 impl Theme {
+    const BUILT_IN: [&'static str; 2] = ["dark", "light"];
+
+    /// Themes can be added as toml files in `$ConfigDir/termchat/themes/<name>.toml`
+    fn themes_dir() -> Option<PathBuf> {
+        Some(config_dir()?.join("themes"))
+    }
+
+    /// Load a theme by its name, theme files take precedence over the built-in themes
+    pub fn load(name: &str) -> Result<Theme> {
+        // The name is a file of the themes dir, it can not point outside it
+        if name.contains(['/', '\\']) || name.contains("..") {
+            return Err(format!("Invalid theme name '{}'", name).into())
+        }
+
+        if let Some(themes_dir) = Self::themes_dir() {
+            let theme_path = themes_dir.join(format!("{}.toml", name));
+            if theme_path.is_file() {
+                let theme = std::fs::read_to_string(&theme_path)?;
+                return toml::from_str(&theme)
+                    .map_err(|e| format!("Theme file '{}': {}", theme_path.display(), e).into())
+            }
+        }
+
+        match name.to_lowercase().as_str() {
+            "dark" => Ok(Self::dark_theme()),
+            "light" => Ok(Self::light_theme()),
+            _ => Err(format!(
+                "Unknown theme '{}', the available themes are: {}",
+                name,
+                Self::available().join(", ")
+            )
+            .into()),
+        }
+    }
+
+    /// Names of the built-in themes and of the theme files
+    pub fn available() -> Vec<String> {
+        let mut names = Self::BUILT_IN.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        if let Some(entries) = Self::themes_dir().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            let mut file_names = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .filter(|name| !names.contains(name))
+                .collect::<Vec<_>>();
+            file_names.sort();
+            names.extend(file_names);
+        }
+        names
+    }
+
     fn dark_theme() -> Self {
         Self {
             message_colors: vec![Color::Blue, Color::Yellow, Color::Cyan, Color::Magenta],
@@ -140,3 +605,130 @@ impl Theme {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_message_colors() {
+        let config = Config::from_toml(Some("[theme]\nmessage_colors = []"), &[]).unwrap();
+        assert_eq!(config.theme.message_colors.len(), Theme::default().message_colors.len());
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].starts_with("Config line 2: invalid 'theme.message_colors'"));
+    }
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn environment_over_file() {
+        let variables = variables(&[
+            ("TERMCHAT_USER_NAME", "bob"),
+            ("TERMCHAT_LAYOUT__COMPACT", "true"),
+            ("TERMCHAT_KEYS__CTRL_Q", "quit"),
+        ]);
+        let config = Config::from_toml(Some("user_name = 'alice'\nmouse = true"), &variables);
+        let config = config.unwrap();
+        assert_eq!(config.user_name, "bob");
+        assert!(config.mouse);
+        assert!(config.layout.compact);
+        assert_eq!(toml::to_string(&config.keys).unwrap(), "ctrl-q = \"quit\"\n");
+        assert!(config.warnings.is_empty());
+    }
+
+    #[test]
+    fn unknown_environment_variables() {
+        let variables = variables(&[
+            ("TERMCHAT_LOG", "debug"),
+            ("TERMCHAT_LAYOUT__WIDTH", "3"),
+            ("TERMCHAT_MOUSE", "yes"),
+        ]);
+        let config = Config::from_toml(None, &variables).unwrap();
+        assert!(!config.mouse);
+        // Only the invalid value of a known key is reported
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].starts_with("Environment variable TERMCHAT_MOUSE"));
+    }
+
+    #[test]
+    fn environment_table_names() {
+        let variables = variables(&[
+            ("TERMCHAT_ALIASES__SEND_FILE", "send"),
+            ("TERMCHAT_KEYS__ALT_Q", "quit"),
+            ("TERMCHAT_THEME__DATE_COLOR", "Red"),
+        ]);
+        let config = Config::from_toml(Some("theme = 'light'"), &variables).unwrap();
+        assert_eq!(config.aliases.get("send_file").map(String::as_str), Some("send"));
+        assert_eq!(toml::to_string(&config.keys).unwrap(), "alt-q = \"quit\"\n");
+        assert_eq!(config.theme.chat_panel_color, Color::Black);
+        assert_eq!(config.warnings.len(), 1);
+        let warning = "TERMCHAT_THEME__DATE_COLOR: 'theme' is not a table to set 'date_color'";
+        assert!(config.warnings[0].ends_with(warning));
+    }
+
+    #[test]
+    fn unknown_and_invalid_keys() {
+        let content =
+            "user_name = 'alice'\nmouse = 'yes'\ncolor = 3\n\n[theme]\nwidth = 3\ndate_color = 'Red'";
+        let config = Config::from_toml(Some(content), &[]).unwrap();
+        // The valid keys are still read
+        assert_eq!(config.user_name, "alice");
+        assert_eq!(config.theme.date_color, Color::Red);
+        assert!(!config.mouse);
+        assert_eq!(config.warnings.len(), 3);
+        assert!(config.warnings[0].starts_with("Config line 2: invalid 'mouse'"));
+        assert_eq!(config.warnings[1], "Config line 3: unknown key 'color'");
+        assert_eq!(config.warnings[2], "Config line 6: unknown key 'theme.width'");
+    }
+
+    #[test]
+    fn invalid_field_keeps_the_table() {
+        let content = "[theme]\ndate_color = 'Red'\nchat_panel_color = 3\ncommand_color = 'Blue'";
+        let config = Config::from_toml(Some(content), &[]).unwrap();
+        assert_eq!(config.theme.date_color, Color::Red);
+        assert_eq!(config.theme.chat_panel_color, Theme::default().chat_panel_color);
+        assert_eq!(config.theme.command_color, Color::Blue);
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].starts_with("Config line 3: invalid 'theme.chat_panel_color'"));
+    }
+
+    #[test]
+    fn unparsable_file() {
+        assert!(Config::from_toml(Some("user_name = "), &[]).is_err());
+    }
+
+    #[test]
+    fn key_lines() {
+        let content = "mouse = true\n[theme]\n  \"date_color\" = 'Red'\n[keys]\nmouse = 'quit'";
+        assert_eq!(key_line(content, "mouse"), Some(1));
+        assert_eq!(key_line(content, "theme.date_color"), Some(3));
+        assert_eq!(key_line(content, "keys.mouse"), Some(5));
+        assert_eq!(key_line(content, "theme.command_color"), None);
+
+        let content = "# mouse = 3\n theme . \"date_color\" = 'Red'\nlayout = { compact = 3 }";
+        assert_eq!(key_line(content, "mouse"), None);
+        assert_eq!(key_line(content, "theme.date_color"), Some(2));
+        assert_eq!(key_line(content, "layout.compact"), Some(3));
+    }
+
+    #[test]
+    fn dotted_keys_and_inline_tables() {
+        let content = "theme.date_color = 'Red'\ntheme.command_color = 3\nlayout = { compact = 3 }";
+        let config = Config::from_toml(Some(content), &[]).unwrap();
+        assert_eq!(config.theme.date_color, Color::Red);
+        assert_eq!(config.warnings.len(), 2);
+        assert!(config.warnings[0].starts_with("Config line 2: invalid 'theme.command_color'"));
+        assert!(config.warnings[1].starts_with("Config line 3: invalid 'layout.compact'"));
+    }
+
+    #[test]
+    fn theme_names_outside_the_themes_dir() {
+        for name in ["../config", "dir/theme", "..", "dir\\theme"] {
+            let error = Theme::load(name).err().unwrap().to_string();
+            assert_eq!(error, format!("Invalid theme name '{}'", name));
+        }
+        assert!(Theme::load("dark").is_ok());
+    }
+}
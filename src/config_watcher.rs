@@ -0,0 +1,56 @@
+use crate::util::Result;
+
+use std::path::{PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+const FILE_SAMPLING_TIMEOUT: u64 = 500; //ms
+
+/// Notifies each time the file is modified, checking periodically its modification time
+pub struct ConfigWatcher {
+    // Dropped to stop the thread, it wakes up without waiting the sampling timeout
+    watcher_thread_stop: Option<Sender<()>>,
+    watcher_thread_handle: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    pub fn new<C>(file_path: PathBuf, change_callback: C) -> Result<ConfigWatcher>
+    where C: Fn() + Send + 'static {
+        let (watcher_thread_stop, stop_receiver) = mpsc::channel();
+        let watcher_thread_handle = {
+            let timeout = Duration::from_millis(FILE_SAMPLING_TIMEOUT);
+            let modified = move || -> Option<SystemTime> {
+                std::fs::metadata(&file_path).and_then(|metadata| metadata.modified()).ok()
+            };
+            thread::Builder::new().name("termchat: config watcher".into()).spawn(move || {
+                let mut last_modified = modified();
+                while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(timeout) {
+                    let current_modified = modified();
+                    // A removed file is not a change, the config keeps its values
+                    if current_modified.is_some() && current_modified != last_modified {
+                        change_callback();
+                    }
+                    last_modified = current_modified;
+                }
+            })
+        }?;
+
+        Ok(ConfigWatcher {
+            watcher_thread_stop: Some(watcher_thread_stop),
+            watcher_thread_handle: Some(watcher_thread_handle),
+        })
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.watcher_thread_stop.take();
+        // the unwrap is safe, beacuse we now the handle is some and this is the only time we take it
+        self.watcher_thread_handle
+            .take()
+            .unwrap()
+            .join()
+            .expect("Error while joining config watcher thread handle");
+    }
+}
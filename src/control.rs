@@ -0,0 +1,285 @@
+use crate::node::{Peer};
+use crate::util::{Result};
+
+use serde::{Deserialize};
+use serde_json::{json, Value};
+
+use std::collections::{HashMap};
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    mpsc::{self, SyncSender},
+    Arc, Mutex,
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration};
+
+// A client that doesn't read the replies can not block the application
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(500);
+
+/// Request of an external tool, written in the socket as a json object per line
+#[derive(Deserialize, Debug)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// Send the text as a message, even if it starts with '?'
+    Send {
+        message: String,
+    },
+    /// Process a command as if the user typed it, as `?send file.txt`
+    Command {
+        command: String,
+    },
+    Peers,
+    /// Receive the chat events until the connection is closed
+    Subscribe,
+}
+
+/// Connection of an external tool, used to reply its requests
+pub struct ControlClient {
+    stream: UnixStream,
+}
+
+impl ControlClient {
+    fn write(&self, value: Value) -> io::Result<()> {
+        writeln!(&self.stream, "{}", value)
+    }
+
+    pub fn reply_ok(&self) -> io::Result<()> {
+        self.write(json!({ "ok": true }))
+    }
+
+    pub fn reply_error(&self, error: &str) -> io::Result<()> {
+        self.write(json!({ "ok": false, "error": error }))
+    }
+
+    pub fn reply_peers(&self, peers: &[Peer]) -> io::Result<()> {
+        let peers = peers
+            .iter()
+            .map(|peer| json!({ "user": peer.name, "address": peer.endpoint.addr().ip() }))
+            .collect::<Vec<_>>();
+        self.write(json!({ "ok": true, "peers": peers }))
+    }
+
+    /// The client receives the chat events from now, written by its own thread
+    pub fn subscribe(self) -> io::Result<Subscriber> {
+        let (events, queued_events) = mpsc::sync_channel::<String>(Subscriber::MAX_QUEUED_EVENTS);
+        let stream = self.stream.try_clone()?;
+        thread::Builder::new().name("termchat: control subscriber".into()).spawn(move || {
+            for line in queued_events {
+                if writeln!(&self.stream, "{}", line).is_err() {
+                    break
+                }
+            }
+        })?;
+        Ok(Subscriber { stream, events })
+    }
+}
+
+/// Client receiving the chat events, its connection is closed once it is dropped
+pub struct Subscriber {
+    stream: UnixStream,
+    events: SyncSender<String>,
+}
+
+impl Subscriber {
+    // Events waiting to be written, a client that doesn't read them is dropped
+    const MAX_QUEUED_EVENTS: usize = 256;
+
+    /// Queues a line of the event log, without waiting it to be written.
+    /// Returns false if the client can not receive more events.
+    pub fn send_event(&self, line: &str) -> bool {
+        self.events.try_send(line.into()).is_ok()
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        // Also wakes up the writer if it is blocked
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+pub struct ControlRequest {
+    pub request: Request,
+    pub client: ControlClient,
+}
+
+/// Unix socket where external tools send requests to a running application
+pub struct ControlServer {
+    path: PathBuf,
+    running: Arc<AtomicBool>,
+    connections: Arc<Mutex<HashMap<usize, UnixStream>>>,
+    accept_thread_handle: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    pub fn new<C>(user_name: &str, request_callback: C) -> Result<ControlServer>
+    where C: Fn(ControlRequest) + Send + Sync + 'static {
+        let dir = socket_dir();
+        // The sockets can run commands, so only the user can access them
+        DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+        check_own_dir(&dir)?;
+        fs::set_permissions(&dir, Permissions::from_mode(0o700))
+            .map_err(|e| format!("'{}' is not accessible: {}", dir.display(), e))?;
+
+        let file_name = format!("{}-{}.sock", user_name.replace('/', "_"), std::process::id());
+        let path = dir.join(file_name);
+        if path.exists() {
+            fs::remove_file(&path)?; // Left by a previous process with the same pid
+        }
+        let listener = UnixListener::bind(&path)?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let accept_thread_handle = {
+            let running = running.clone();
+            let connections = connections.clone();
+            let callback = Arc::new(request_callback);
+            let next_id = AtomicUsize::new(0);
+            thread::Builder::new().name("termchat: control socket".into()).spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::Relaxed) {
+                        break
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => {
+                            // As running out of file descriptors, it could last a while
+                            thread::sleep(ACCEPT_ERROR_DELAY);
+                            continue
+                        }
+                    };
+                    let id = next_id.fetch_add(1, Ordering::Relaxed);
+                    let callback = callback.clone();
+                    let connections = connections.clone();
+                    // If the client can not be read, its connection is closed when dropped.
+                    // Nothing is printed, the terminal belongs to the frontend.
+                    thread::Builder::new()
+                        .name("termchat: control client".into())
+                        .spawn(move || read_requests(id, stream, connections, &*callback))
+                        .ok();
+                }
+            })
+        }?;
+
+        Ok(ControlServer {
+            path,
+            running,
+            connections,
+            accept_thread_handle: Some(accept_thread_handle),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        // Wake up the accept call, that checks again if it is running
+        UnixStream::connect(&self.path).ok();
+        // the unwrap is safe, beacuse we now the handle is some and this is the only time we take it
+        self.accept_thread_handle
+            .take()
+            .unwrap()
+            .join()
+            .expect("Error while joining control socket thread handle");
+
+        // The readers of the clients end once their connection is closed
+        for stream in self.connections.lock().unwrap().values() {
+            stream.shutdown(Shutdown::Both).ok();
+        }
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Directory of the control sockets, as `$XDG_RUNTIME_DIR/termchat`
+fn socket_dir() -> PathBuf {
+    match dirs_next::runtime_dir() {
+        Some(runtime_dir) => runtime_dir.join("termchat"),
+        None => std::env::temp_dir().join(format!("termchat-{}", whoami::username())),
+    }
+}
+
+/// The directory could already exist, created by other user in the shared temporary dir
+fn check_own_dir(dir: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    // Safe, getuid always succeeds
+    let uid = unsafe { libc::getuid() };
+    if !metadata.file_type().is_dir() || metadata.uid() != uid {
+        return Err(format!("'{}' is not a directory owned by the user", dir.display()).into())
+    }
+    Ok(())
+}
+
+fn read_requests(
+    id: usize,
+    stream: UnixStream,
+    connections: Arc<Mutex<HashMap<usize, UnixStream>>>,
+    callback: &dyn Fn(ControlRequest),
+) {
+    let client = || -> io::Result<ControlClient> {
+        let stream = stream.try_clone()?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(ControlClient { stream })
+    };
+
+    if let Ok(connection) = stream.try_clone() {
+        connections.lock().unwrap().insert(id, connection);
+        for line in BufReader::new(&stream).lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let client = match client() {
+                Ok(client) => client,
+                Err(_) => break,
+            };
+            match serde_json::from_str::<Request>(&line) {
+                Ok(request) => callback(ControlRequest { request, client }),
+                Err(e) => {
+                    if client.reply_error(&format!("Invalid request: {}", e)).is_err() {
+                        break
+                    }
+                }
+            }
+        }
+        connections.lock().unwrap().remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriber_not_reading_is_dropped() {
+        let (stream, _not_read) = UnixStream::pair().unwrap();
+        let subscriber = ControlClient { stream }.subscribe().unwrap();
+        let line = "event".repeat(200);
+        // Only the socket buffer and the queue are filled, the sending never blocks
+        let sent = (0..100_000).take_while(|_| subscriber.send_event(&line)).count();
+        assert!(sent < 100_000);
+    }
+
+    #[test]
+    fn socket_dir_not_owned() {
+        let dir = std::env::temp_dir().join(format!("termchat-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+
+        assert!(check_own_dir(&dir).is_ok());
+        assert!(check_own_dir(&link).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
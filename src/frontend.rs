@@ -0,0 +1,42 @@
+pub mod tui;
+pub mod plain;
+
+use crate::state::{State};
+use crate::config::{Config};
+use crate::util::{Error, Result};
+
+use crossterm::event::{Event as TermEvent};
+
+/// Input of the user, given by the frontend
+pub enum InputEvent {
+    /// Keys, mouse and resize events, edit the input of the state as a terminal does
+    Terminal(TermEvent),
+    /// A message selected by the user, None to unselect it
+    SelectMessage(Option<usize>),
+    /// A whole line written by the user, processed as the input sent
+    Line(String),
+    /// The input is finished, with an error in case of failure
+    Close(Option<Error>),
+}
+
+/// Output of the application that is not part of the state
+pub enum Output {
+    /// Sound of a new message, if the terminal bell is enabled
+    Bell,
+    /// Text copied to the clipboard of the terminal
+    Clipboard(String),
+}
+
+/// User interface of the application: shows its state and collects the input of the user.
+pub trait Frontend {
+    /// Called once before drawing, the input is given to the callback until the frontend is dropped
+    fn start(&mut self, config: &Config, input: Box<dyn Fn(InputEvent) + Send>) -> Result<()>;
+
+    /// Called each time the state could have changed.
+    /// The frontend updates the parts of the state that depend on how it is shown,
+    /// as the size of the messages view.
+    fn draw(&mut self, state: &mut State, config: &Config) -> Result<()>;
+
+    /// Called before drawing with each output produced since the last draw
+    fn output(&mut self, output: Output) -> Result<()>;
+}
@@ -0,0 +1,104 @@
+use crate::frontend::{Frontend, InputEvent, Output};
+use crate::state::{State, ChatMessage, MessageType, ProgressState, SystemMessageType};
+use crate::util::{Result};
+use crate::config::{Config};
+
+use std::io::{BufRead, Write};
+
+/// Line mode frontend: prints each message in one line and reads the input by lines.
+/// It does not move the cursor, so it works in dumb terminals and with screen readers.
+pub struct PlainFrontend<W: Write> {
+    out: W,
+    // Messages until this one were already printed, or are pending
+    printed: usize,
+    // Messages not finished yet when they were added, as a running command
+    pending: Vec<usize>,
+}
+
+impl<W: Write> PlainFrontend<W> {
+    pub fn new(out: W) -> PlainFrontend<W> {
+        PlainFrontend { out, printed: 0, pending: Vec::new() }
+    }
+
+    fn print(&mut self, message: &ChatMessage) -> Result<()> {
+        let date = message.date.format("%H:%M:%S");
+        let user = message.user.trim_end_matches(": ");
+        let line = match &message.message_type {
+            MessageType::Connection | MessageType::Disconnection => message.text(),
+            MessageType::System(content, message_type) => {
+                let level = match message_type {
+                    SystemMessageType::Info => "",
+                    SystemMessageType::Warning => " warning",
+                    SystemMessageType::Error => " error",
+                };
+                format!("{}{}: {}", user, level, content)
+            }
+            MessageType::Progress(ProgressState::Completed) => format!("{} completed", user),
+            MessageType::Progress(_) => format!("{} failed", user),
+            MessageType::FileReceived(sender, path) => {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let saved = path.display();
+                format!(
+                    "{}: received '{}' from '{}', saved in '{}'",
+                    user, file_name, sender, saved
+                )
+            }
+            _ => format!("{}: {}", user, message.text()),
+        };
+        // Several lines are joined to keep one message per line
+        writeln!(self.out, "{} {}", date, line.split('\n').collect::<Vec<_>>().join(" | "))?;
+        Ok(())
+    }
+}
+
+/// The messages that change after added are printed once finished
+fn finished(state: &State, index: usize) -> bool {
+    match &state.messages()[index].message_type {
+        MessageType::Progress(_) => !state.is_sending_file(index),
+        MessageType::CommandOutput(output) => output.exit.is_some(),
+        _ => true,
+    }
+}
+
+impl<W: Write> Frontend for PlainFrontend<W> {
+    fn start(&mut self, _config: &Config, input: Box<dyn Fn(InputEvent) + Send>) -> Result<()> {
+        // The thread is not joined, it is blocked reading until a line is written
+        std::thread::Builder::new().name("termchat: plain input reader".into()).spawn(
+            move || {
+                let stdin = std::io::stdin();
+                let mut lines = stdin.lock().lines();
+                loop {
+                    match lines.next() {
+                        Some(Ok(line)) => input(InputEvent::Line(line)),
+                        Some(Err(e)) => break input(InputEvent::Close(Some(e.into()))),
+                        None => break input(InputEvent::Close(None)),
+                    }
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    fn draw(&mut self, state: &mut State, _config: &Config) -> Result<()> {
+        let messages = state.messages();
+        let new_messages = self.printed..messages.len();
+        self.printed = messages.len();
+        for index in std::mem::take(&mut self.pending).into_iter().chain(new_messages) {
+            match finished(state, index) {
+                true => self.print(&state.messages()[index])?,
+                false => self.pending.push(index),
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn output(&mut self, output: Output) -> Result<()> {
+        match output {
+            Output::Bell => write!(self.out, "\x07")?,
+            // The messages can not be selected to be copied in this mode
+            Output::Clipboard(_) => (),
+        }
+        Ok(())
+    }
+}
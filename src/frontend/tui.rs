@@ -0,0 +1,113 @@
+use crate::frontend::{Frontend, InputEvent, Output};
+use crate::ui::{self, MessagesLayout};
+use crate::state::{State};
+use crate::util::{Result};
+use crate::config::{Config};
+use crate::terminal_events::{TerminalEventCollector};
+
+use crossterm::terminal::{self};
+use crossterm::event::{
+    EnableMouseCapture, DisableMouseCapture, Event as TermEvent, MouseButton, MouseEvent,
+};
+use crossterm::{ExecutableCommand};
+
+use tui::{Terminal};
+use tui::backend::{CrosstermBackend};
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Default frontend, drawing the panels of the chat in the alternate screen of the terminal
+pub struct TuiFrontend<W: Write> {
+    terminal: Terminal<CrosstermBackend<W>>,
+    started: bool,
+    mouse: bool,
+    // Shared with the event collector to find the message under the mouse
+    messages_layout: Arc<Mutex<MessagesLayout>>,
+    terminal_events: Option<TerminalEventCollector>,
+}
+
+impl<W: Write> TuiFrontend<W> {
+    pub fn new(out: W) -> Result<TuiFrontend<W>> {
+        Ok(TuiFrontend {
+            terminal: Terminal::new(CrosstermBackend::new(out))?,
+            started: false,
+            mouse: false,
+            messages_layout: Arc::default(),
+            terminal_events: None,
+        })
+    }
+}
+
+impl<W: Write> Frontend for TuiFrontend<W> {
+    fn start(&mut self, config: &Config, input: Box<dyn Fn(InputEvent) + Send>) -> Result<()> {
+        terminal::enable_raw_mode()?;
+        self.started = true;
+        let out = self.terminal.backend_mut();
+        out.execute(terminal::EnterAlternateScreen)?;
+        if config.mouse {
+            out.execute(EnableMouseCapture)?;
+            self.mouse = true;
+        }
+
+        let messages_layout = self.messages_layout.clone();
+        self.terminal_events = Some(TerminalEventCollector::new(move |term_event| {
+            input(match term_event {
+                Ok(TermEvent::Mouse(MouseEvent::Down(MouseButton::Left, column, row, _))) => {
+                    let message = messages_layout.lock().unwrap().message_at(column, row);
+                    InputEvent::SelectMessage(message)
+                }
+                Ok(event) => InputEvent::Terminal(event),
+                Err(e) => InputEvent::Close(Some(e)),
+            })
+        })?);
+        Ok(())
+    }
+
+    fn draw(&mut self, state: &mut State, config: &Config) -> Result<()> {
+        let mut messages_layout = MessagesLayout::default();
+        self.terminal.draw(|frame| {
+            messages_layout = ui::draw(frame, state, frame.size(), &config.theme, &config.layout)
+        })?;
+        state.update_messages_view(messages_layout.total_lines(), messages_layout.height());
+        *self.messages_layout.lock().unwrap() = messages_layout;
+        Ok(())
+    }
+
+    fn output(&mut self, output: Output) -> Result<()> {
+        let out = self.terminal.backend_mut();
+        match output {
+            Output::Bell => write!(out, "\x07")?,
+            // OSC 52 sequence, it also works through ssh
+            Output::Clipboard(text) => write!(out, "\x1b]52;c;{}\x07", base64::encode(text))?,
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for TuiFrontend<W> {
+    fn drop(&mut self) {
+        // Stop reading the terminal before recovering it
+        self.terminal_events.take();
+        if !self.started {
+            return
+        }
+        if self.mouse {
+            self.terminal
+                .backend_mut()
+                .execute(DisableMouseCapture)
+                .expect("Could not execute to stdout");
+        }
+        self.terminal
+            .backend_mut()
+            .execute(terminal::LeaveAlternateScreen)
+            .expect("Could not execute to stdout");
+        terminal::disable_raw_mode().expect("Terminal doesn't support to disable raw mode");
+        if std::thread::panicking() {
+            eprintln!(
+                "termchat paniced, to log the error you can redirect stderror to a file, example: termchat 2> termchat_log",
+            );
+        }
+    }
+}
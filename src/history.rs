@@ -0,0 +1,139 @@
+use crate::util::{Result};
+
+use std::io::{ErrorKind, Write};
+use std::path::{PathBuf};
+
+/// Lines sent by the user, kept across sessions in the termchat data dir.
+#[derive(Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    file_path: Option<PathBuf>,
+}
+
+impl InputHistory {
+    const FILE_NAME: &'static str = "history";
+    const MAX_ENTRIES: usize = 1000;
+
+    /// Load the history from disk.
+    /// If the history file does not exist yet an empty history is returned.
+    /// If there is no data dir in this system, the history is only kept in memory.
+    pub fn load() -> Result<InputHistory> {
+        let file_path = match dirs_next::data_dir() {
+            Some(data_dir) => data_dir.join("termchat").join(Self::FILE_NAME),
+            None => return Ok(InputHistory::default()),
+        };
+
+        let mut entries = match std::fs::read_to_string(&file_path) {
+            Ok(content) => content.lines().map(unescape).collect::<Vec<_>>(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if entries.len() > Self::MAX_ENTRIES {
+            // Keep the file from growing forever
+            entries.drain(..entries.len() - Self::MAX_ENTRIES);
+            let mut content =
+                entries.iter().map(|entry| escape(entry)).collect::<Vec<_>>().join("\n");
+            content.push('\n');
+            std::fs::write(&file_path, content)?;
+        }
+
+        Ok(InputHistory { entries, file_path: Some(file_path) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|entry| entry.as_str())
+    }
+
+    /// Add a new entry and append it to the history file.
+    /// Repeating the last entry is not stored twice.
+    pub fn push(&mut self, entry: String) -> Result<()> {
+        if self.entries.last() == Some(&entry) {
+            return Ok(())
+        }
+
+        if let Some(file_path) = &self.file_path {
+            if let Some(dir) = file_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(file_path)?;
+            writeln!(file, "{}", escape(&entry))?;
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Search backwards, from the entry previous to `before`, for an entry containing `query`.
+    pub fn find_backwards(&self, query: &str, before: usize) -> Option<usize> {
+        let before = before.min(self.entries.len());
+        self.entries[..before].iter().rposition(|entry| entry.contains(query))
+    }
+}
+
+/// Entries can be multi-line, so they are stored in the file with the new lines escaped.
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '\\' => match chars.next() {
+                Some('n') => entry.push('\n'),
+                Some(other) => entry.push(other),
+                None => entry.push('\\'),
+            },
+            _ => entry.push(current_char),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> InputHistory {
+        let mut history = InputHistory::default();
+        for entry in entries {
+            history.push(entry.to_string()).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn escape_multi_line_entries() {
+        for entry in ["one line", "two\nlines", "back\\slash", "\\n literal", "end\\"] {
+            let escaped = escape(entry);
+            assert!(!escaped.contains('\n'));
+            assert_eq!(unescape(&escaped), entry);
+        }
+    }
+
+    #[test]
+    fn repeated_entry_stored_once() {
+        let history = history(&["hello", "hello", "bye", "hello"]);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(2), Some("hello"));
+    }
+
+    #[test]
+    fn find_backwards() {
+        let history = history(&["?send a.txt", "hello", "?send b.txt", "bye"]);
+        assert_eq!(history.find_backwards("send", history.len()), Some(2));
+        assert_eq!(history.find_backwards("send", 2), Some(0));
+        assert_eq!(history.find_backwards("send", 0), None);
+        assert_eq!(history.find_backwards("nothing", history.len()), None);
+    }
+}
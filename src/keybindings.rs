@@ -0,0 +1,325 @@
+use crate::util::{Result};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Serialize, Deserialize};
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Actions that can be bound to a key chord in the `[keys]` config table
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyAction {
+    Quit,
+    Send,
+    NewLine,
+    Complete,
+    CompletePrevious,
+    CursorLeft,
+    CursorRight,
+    CursorWordLeft,
+    CursorWordRight,
+    CursorStart,
+    CursorEnd,
+    Delete,
+    DeletePrevious,
+    KillWordLeft,
+    KillToStart,
+    KillToEnd,
+    Yank,
+    YankPop,
+    HistoryPrevious,
+    HistoryNext,
+    HistorySearch,
+    ScrollUp,
+    ScrollDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollTop,
+    ScrollBottom,
+    ToggleVideoPanel,
+    ReplyMessage,
+    ReactMessage,
+    CopyMessage,
+    /// Removes a default binding
+    None,
+}
+
+/// Key chords mapped to actions, as written in the config file.
+/// Only the bindings changed by the user are written,
+/// they are added over the default ones when the key map is resolved.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(transparent)]
+pub struct KeyBindings(BTreeMap<String, KeyAction>);
+
+const DEFAULT_BINDINGS: &[(&str, KeyAction)] = &[
+    ("ctrl-c", KeyAction::Quit),
+    ("enter", KeyAction::Send),
+    ("alt-enter", KeyAction::NewLine),
+    ("ctrl-j", KeyAction::NewLine),
+    ("tab", KeyAction::Complete),
+    ("backtab", KeyAction::CompletePrevious),
+    ("left", KeyAction::CursorLeft),
+    ("right", KeyAction::CursorRight),
+    ("ctrl-left", KeyAction::CursorWordLeft),
+    ("alt-b", KeyAction::CursorWordLeft),
+    ("ctrl-right", KeyAction::CursorWordRight),
+    ("alt-f", KeyAction::CursorWordRight),
+    ("home", KeyAction::CursorStart),
+    ("ctrl-a", KeyAction::CursorStart),
+    ("end", KeyAction::CursorEnd),
+    ("ctrl-e", KeyAction::CursorEnd),
+    ("delete", KeyAction::Delete),
+    ("backspace", KeyAction::DeletePrevious),
+    ("ctrl-w", KeyAction::KillWordLeft),
+    ("ctrl-u", KeyAction::KillToStart),
+    ("ctrl-k", KeyAction::KillToEnd),
+    ("ctrl-y", KeyAction::Yank),
+    ("alt-y", KeyAction::YankPop),
+    ("up", KeyAction::HistoryPrevious),
+    ("ctrl-p", KeyAction::HistoryPrevious),
+    ("down", KeyAction::HistoryNext),
+    ("ctrl-n", KeyAction::HistoryNext),
+    ("ctrl-r", KeyAction::HistorySearch),
+    ("ctrl-up", KeyAction::ScrollUp),
+    ("ctrl-down", KeyAction::ScrollDown),
+    ("pageup", KeyAction::ScrollPageUp),
+    ("pagedown", KeyAction::ScrollPageDown),
+    ("ctrl-home", KeyAction::ScrollTop),
+    ("ctrl-end", KeyAction::ScrollBottom),
+    ("alt-v", KeyAction::ToggleVideoPanel),
+    ("alt-r", KeyAction::ReplyMessage),
+    ("alt-l", KeyAction::ReactMessage),
+    ("alt-c", KeyAction::CopyMessage),
+];
+
+impl KeyBindings {
+    /// Resolve the bindings over the default ones.
+    /// Fails if a chord can not be parsed, if the same chord written in different ways
+    /// is bound to different actions, or if a chord would prevent writing a character.
+    pub fn key_map(&self) -> Result<KeyMap> {
+        let mut map = HashMap::new();
+        for (chord, action) in DEFAULT_BINDINGS {
+            map.insert(parse_chord(chord)?, *action);
+        }
+
+        let mut configured: HashMap<KeyEvent, (&str, KeyAction)> = HashMap::new();
+        for (chord, action) in &self.0 {
+            let key = parse_chord(chord)?;
+            if let Some((other_chord, other_action)) = configured.get(&key) {
+                if *other_action != *action {
+                    return Err(format!(
+                        "Key '{}' is bound to '{}' and as '{}' to '{}'",
+                        other_chord,
+                        action_name(*other_action),
+                        chord,
+                        action_name(*action),
+                    )
+                    .into())
+                }
+            }
+            if let KeyCode::Char(character) = key.code {
+                if key.modifiers.is_empty() && *action != KeyAction::None {
+                    return Err(format!(
+                        "Key '{}' can not be bound to '{}', it is needed to write '{}'",
+                        chord,
+                        action_name(*action),
+                        character,
+                    )
+                    .into())
+                }
+            }
+            configured.insert(key, (chord, *action));
+        }
+
+        for (key, (_, action)) in configured {
+            match action {
+                KeyAction::None => map.remove(&key),
+                action => map.insert(key, action),
+            };
+        }
+
+        Ok(KeyMap(map))
+    }
+}
+
+fn action_name(action: KeyAction) -> String {
+    // Safe unwrap: an unit variant always serializes to a string
+    toml::Value::try_from(action).unwrap().as_str().unwrap().into()
+}
+
+/// Parse chords as `ctrl-c`, `Alt+Enter`, `shift-tab` or `pagedown`
+fn parse_chord(chord: &str) -> Result<KeyEvent> {
+    const MODIFIERS: [(&str, KeyModifiers); 3] = [
+        ("ctrl", KeyModifiers::CONTROL),
+        ("alt", KeyModifiers::ALT),
+        ("shift", KeyModifiers::SHIFT),
+    ];
+
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = chord;
+    while let Some((name, modifier)) = MODIFIERS.iter().find(|(name, _)| has_modifier(rest, name)) {
+        modifiers |= *modifier;
+        rest = &rest[name.len() + 1..];
+    }
+
+    let code = match rest.to_lowercase().as_str() {
+        "enter" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" | "ins" => KeyCode::Insert,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "space" => KeyCode::Char(' '),
+        name => match (name.strip_prefix('f'), rest.chars().count()) {
+            (Some(number), _) if !number.is_empty() => match number.parse::<u8>() {
+                Ok(number) if (1..=12).contains(&number) => KeyCode::F(number),
+                _ => return Err(format!("Unknown key '{}' in '{}'", rest, chord).into()),
+            },
+            (_, 1) => KeyCode::Char(rest.chars().next().unwrap()),
+            _ => return Err(format!("Unknown key '{}' in '{}'", rest, chord).into()),
+        },
+    };
+
+    Ok(normalize(KeyEvent { code, modifiers }))
+}
+
+/// The modifier must be followed by a separator and a key: `ctrl-c`, `ctrl+c`
+fn has_modifier(chord: &str, name: &str) -> bool {
+    chord.len() > name.len() + 1
+        && chord.get(..name.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(name))
+        && matches!(chord.as_bytes()[name.len()], b'-' | b'+')
+}
+
+/// Terminals report chars already in upper case when shift is pressed,
+/// and in lower case when other modifier is pressed.
+/// Enter pressed with alt is reported as the `\r` char, and shift-tab as backtab with shift.
+fn normalize(mut key: KeyEvent) -> KeyEvent {
+    match key.code {
+        KeyCode::Char('\r') | KeyCode::Char('\n') => key.code = KeyCode::Enter,
+        KeyCode::Tab if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            key.code = KeyCode::BackTab;
+            key.modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyCode::BackTab => key.modifiers.remove(KeyModifiers::SHIFT),
+        _ => (),
+    }
+    if let KeyCode::Char(character) = key.code {
+        if key.modifiers.contains(KeyModifiers::SHIFT) {
+            key.code = KeyCode::Char(character.to_ascii_uppercase());
+            key.modifiers.remove(KeyModifiers::SHIFT);
+        }
+        else if !key.modifiers.is_empty() {
+            key.code = KeyCode::Char(character.to_ascii_lowercase());
+        }
+    }
+    key
+}
+
+/// Resolved bindings used to find the action of a pressed key
+pub struct KeyMap(HashMap<KeyEvent, KeyAction>);
+
+impl KeyMap {
+    pub fn action(&self, key: KeyEvent) -> Option<KeyAction> {
+        self.0.get(&normalize(key)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent { code, modifiers }
+    }
+
+    #[test]
+    fn new_line_keys() {
+        let map = KeyBindings::default().key_map().unwrap();
+        // As crossterm reports Alt-Enter and Ctrl-J
+        let alt_enter = key(KeyCode::Char('\r'), KeyModifiers::ALT);
+        let ctrl_j = key(KeyCode::Char('j'), KeyModifiers::CONTROL);
+        assert_eq!(map.action(alt_enter), Some(KeyAction::NewLine));
+        assert_eq!(map.action(ctrl_j), Some(KeyAction::NewLine));
+        assert_eq!(map.action(key(KeyCode::Enter, KeyModifiers::NONE)), Some(KeyAction::Send));
+    }
+
+    #[test]
+    fn overrides_over_defaults() {
+        let bindings: KeyBindings = toml::from_str("esc = 'quit'\nctrl-c = 'none'").unwrap();
+        let map = bindings.key_map().unwrap();
+        assert_eq!(map.action(key(KeyCode::Esc, KeyModifiers::NONE)), Some(KeyAction::Quit));
+        assert_eq!(map.action(key(KeyCode::Char('c'), KeyModifiers::CONTROL)), None);
+        let ctrl_a = key(KeyCode::Char('a'), KeyModifiers::CONTROL);
+        assert_eq!(map.action(ctrl_a), Some(KeyAction::CursorStart));
+    }
+
+    #[test]
+    fn backtab_with_shift() {
+        let map = KeyBindings::default().key_map().unwrap();
+        // As crossterm reports Shift-Tab
+        let backtab = key(KeyCode::BackTab, KeyModifiers::SHIFT);
+        assert_eq!(map.action(backtab), Some(KeyAction::CompletePrevious));
+        assert_eq!(parse_chord("shift-tab").ok(), parse_chord("backtab").ok());
+    }
+
+    #[test]
+    fn chord_spellings() {
+        let ctrl_c = key(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(parse_chord("ctrl-c").unwrap(), ctrl_c);
+        assert_eq!(parse_chord("Ctrl+C").unwrap(), ctrl_c);
+        let alt_shift_x = key(KeyCode::Char('X'), KeyModifiers::ALT);
+        assert_eq!(parse_chord("alt-shift-x").unwrap(), alt_shift_x);
+        assert_eq!(parse_chord("F5").unwrap(), key(KeyCode::F(5), KeyModifiers::NONE));
+        assert_eq!(parse_chord("space").unwrap(), key(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(parse_chord("-").unwrap(), key(KeyCode::Char('-'), KeyModifiers::NONE));
+        assert_eq!(parse_chord("ctrl--").unwrap(), key(KeyCode::Char('-'), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn unknown_chords() {
+        assert!(parse_chord("f13").is_err());
+        assert!(parse_chord("ctrl-").is_err());
+        assert!(parse_chord("hyper-x").is_err());
+        assert!(parse_chord("pgdown").is_err());
+    }
+
+    #[test]
+    fn normalize_reported_chars() {
+        // As crossterm reports Shift-A and Ctrl-Shift-A
+        let shift_a = key(KeyCode::Char('A'), KeyModifiers::SHIFT);
+        assert_eq!(normalize(shift_a), key(KeyCode::Char('A'), KeyModifiers::NONE));
+        let ctrl_shift_a = key(KeyCode::Char('A'), KeyModifiers::CONTROL | KeyModifiers::SHIFT);
+        assert_eq!(normalize(ctrl_shift_a), key(KeyCode::Char('A'), KeyModifiers::CONTROL));
+        let ctrl_a = key(KeyCode::Char('A'), KeyModifiers::CONTROL);
+        assert_eq!(normalize(ctrl_a), key(KeyCode::Char('a'), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn conflicting_spellings() {
+        let bindings: KeyBindings = toml::from_str("ctrl-x = 'quit'\n'Ctrl+X' = 'send'").unwrap();
+        assert!(bindings.key_map().is_err());
+        let bindings: KeyBindings = toml::from_str("ctrl-x = 'quit'\n'Ctrl+X' = 'quit'").unwrap();
+        let map = bindings.key_map().unwrap();
+        let ctrl_x = key(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        assert_eq!(map.action(ctrl_x), Some(KeyAction::Quit));
+    }
+
+    #[test]
+    fn plain_char_bindings() {
+        let bindings: KeyBindings = toml::from_str("q = 'quit'").unwrap();
+        assert!(bindings.key_map().is_err());
+        let bindings: KeyBindings = toml::from_str("q = 'none'").unwrap();
+        assert!(bindings.key_map().is_ok());
+        let bindings: KeyBindings = toml::from_str("shift-q = 'quit'").unwrap();
+        assert!(bindings.key_map().is_err());
+    }
+}
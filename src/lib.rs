@@ -1,11 +1,34 @@
+#[cfg(feature = "tui")]
 pub mod application;
+#[cfg(feature = "tui")]
 mod state;
+#[cfg(feature = "tui")]
 mod terminal_events;
+#[cfg(feature = "tui")]
+mod config_watcher;
 mod message;
-mod renderer;
+#[cfg(feature = "tui")]
+pub mod frontend;
+#[cfg(feature = "tui")]
 mod action;
+#[cfg(feature = "tui")]
 mod commands;
+#[cfg(feature = "tui")]
 mod ui;
 mod util;
 mod encoder;
+#[cfg(feature = "tui")]
+mod history;
+#[cfg(feature = "tui")]
+mod completion;
+#[cfg(feature = "tui")]
+mod keybindings;
+#[cfg(feature = "tui")]
+mod vi;
+#[cfg(feature = "tui")]
 pub mod config;
+pub mod node;
+pub mod oneshot;
+pub mod listen;
+#[cfg(all(unix, feature = "tui"))]
+pub mod control;
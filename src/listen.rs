@@ -0,0 +1,179 @@
+use crate::node::{ChatNode, ChatEvent, NodeConfig, Peer, TermScreen};
+use crate::util::{Result};
+
+use serde::{Serialize};
+
+use message_io::network::{Endpoint};
+
+use std::collections::{HashSet};
+use std::io::{ErrorKind, Write};
+use std::path::{PathBuf};
+
+/// How `termchat listen` writes each event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json, // one object per line
+}
+
+/// Event written by `termchat listen`
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Record {
+    Connected { user: String },
+    Disconnected { user: String },
+    Message { user: String, content: String },
+    FileReceiving { user: String, file: String },
+    FileReceived { user: String, file: String, path: PathBuf },
+    FileError { user: String, file: String },
+    StreamStarted { user: String },
+    StreamEnded { user: String },
+    TerminalShared { user: String, command: String },
+    TerminalEnded { user: String },
+    Tail { user: String, file: String, lines: Vec<String>, skipped_lines: usize },
+    Error { message: String },
+}
+
+impl Record {
+    fn text(&self) -> String {
+        match self {
+            Record::Connected { user } => format!("{} is online", user),
+            Record::Disconnected { user } => format!("{} is offline", user),
+            Record::Message { user, content } => format!("{}: {}", user, content),
+            Record::FileReceiving { user, file } => format!("{} is sending '{}'", user, file),
+            Record::FileReceived { user, file, path } => {
+                format!("{} sent '{}', saved in '{}'", user, file, path.display())
+            }
+            Record::FileError { user, file } => {
+                format!("{} had an error while sending '{}'", user, file)
+            }
+            Record::StreamStarted { user } => format!("{} started a video stream", user),
+            Record::StreamEnded { user } => format!("{} stopped the video stream", user),
+            Record::TerminalShared { user, command } => {
+                format!("{} is sharing a terminal running '{}'", user, command)
+            }
+            Record::TerminalEnded { user } => format!("{} stopped sharing the terminal", user),
+            Record::Tail { user, file, lines, skipped_lines } => {
+                let mut text = format!("{} [{}]", user, file);
+                if *skipped_lines > 0 {
+                    text.push_str(&format!(" [{} lines skipped]", skipped_lines));
+                }
+                text.push_str(": ");
+                text.push_str(&lines.join("\n"));
+                text
+            }
+            Record::Error { message } => format!("error: {}", message),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    time: String,
+    #[serde(flatten)]
+    record: &'a Record,
+}
+
+/// Events written by `termchat listen` and sent to the subscribers of the control socket
+pub(crate) struct EventLog {
+    // Users sending a video stream or sharing a terminal, only the start and the end are written
+    streams: HashSet<Endpoint>,
+    terminals: HashSet<Endpoint>,
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog { streams: HashSet::new(), terminals: HashSet::new() }
+    }
+
+    /// Returns the line that represents the event, if it must be written
+    pub fn line(&mut self, event: &ChatEvent, format: Format) -> Result<Option<String>> {
+        let record = match self.record(event) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        let time = chrono::Local::now();
+        let line = match format {
+            Format::Text => {
+                // One line per event, to be processed line by line
+                let text = record.text().lines().collect::<Vec<_>>().join(" | ");
+                format!("{} {}", time.format("%H:%M:%S"), text)
+            }
+            Format::Json => {
+                serde_json::to_string(&Line { time: time.to_rfc3339(), record: &record })?
+            }
+        };
+        Ok(Some(line))
+    }
+
+    fn record(&mut self, event: &ChatEvent) -> Option<Record> {
+        let user = |peer: &Peer| peer.name.clone();
+        let record = match event {
+            ChatEvent::Connected(peer) => Record::Connected { user: user(peer) },
+            ChatEvent::Disconnected(peer) => {
+                self.streams.remove(&peer.endpoint);
+                self.terminals.remove(&peer.endpoint);
+                Record::Disconnected { user: user(peer) }
+            }
+            ChatEvent::Message(peer, content) => {
+                Record::Message { user: user(peer), content: content.clone() }
+            }
+            ChatEvent::FileReceiving(peer, file) => {
+                Record::FileReceiving { user: user(peer), file: file.clone() }
+            }
+            ChatEvent::FileReceived(peer, path) => {
+                let file = path.file_name().unwrap_or_default().to_string_lossy().into();
+                Record::FileReceived { user: user(peer), file, path: path.clone() }
+            }
+            ChatEvent::FileReceiveError(peer, file) => {
+                Record::FileError { user: user(peer), file: file.clone() }
+            }
+            ChatEvent::Stream(peer, Some(_)) if self.streams.insert(peer.endpoint) => {
+                Record::StreamStarted { user: user(peer) }
+            }
+            ChatEvent::Stream(peer, None) if self.streams.remove(&peer.endpoint) => {
+                Record::StreamEnded { user: user(peer) }
+            }
+            ChatEvent::TermScreen(peer, TermScreen::Contents(command, ..))
+                if self.terminals.insert(peer.endpoint) =>
+            {
+                Record::TerminalShared { user: user(peer), command: command.clone() }
+            }
+            ChatEvent::TermScreen(peer, TermScreen::End)
+                if self.terminals.remove(&peer.endpoint) =>
+            {
+                Record::TerminalEnded { user: user(peer) }
+            }
+            ChatEvent::TailLines(peer, file, lines, skipped_lines) => Record::Tail {
+                user: user(peer),
+                file: file.clone(),
+                lines: lines.clone(),
+                skipped_lines: *skipped_lines,
+            },
+            ChatEvent::Error(message) => Record::Error { message: message.clone() },
+            _ => return None,
+        };
+        Some(record)
+    }
+}
+
+/// Joins the chat without input, writing the events to `out` until the process is killed
+/// or `out` is closed.
+pub fn listen(config: NodeConfig, format: Format, mut out: impl Write) -> Result<()> {
+    let node = ChatNode::new(config)?;
+    let mut log = EventLog::new();
+
+    // Safe unwrap, the node was not created with a callback
+    for event in node.events().unwrap() {
+        if let Some(line) = log.line(&event, format)? {
+            match writeln!(out, "{}", line).and_then(|_| out.flush()) {
+                Ok(()) => (),
+                // The reader of the output finished, as `head` does
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,27 @@
 use termchat::application::{Application};
+use termchat::frontend::tui::{TuiFrontend};
+use termchat::frontend::plain::{PlainFrontend};
 use termchat::config::Config;
+use termchat::node::{NodeConfig, Peer};
+use termchat::oneshot::{self};
+use termchat::listen::{self, Format};
 
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
 use std::net::{SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::time::{Duration};
+
+const EXIT_ERROR: i32 = 1;
+const EXIT_NO_USERS: i32 = 2;
+
+/// What termchat does, the chat by default
+enum Mode {
+    Chat { plain: bool },
+    Send { wait: Duration, to: Option<String>, message: String },
+    SendFile { wait: Duration, path: PathBuf },
+    Listen(Format),
+}
 
 fn main() {
     let matches = App::new(clap::crate_name!())
@@ -12,6 +30,7 @@ fn main() {
         .about(clap::crate_description!())
         .arg(
             Arg::with_name("discovery")
+                .global(true)
                 .long("discovery")
                 .short("d")
                 .takes_value(true)
@@ -23,6 +42,7 @@ fn main() {
         )
         .arg(
             Arg::with_name("tcp_server_port")
+                .global(true)
                 .long("tcp-server-port")
                 .short("t")
                 .takes_value(true)
@@ -34,6 +54,7 @@ fn main() {
         )
         .arg(
             Arg::with_name("username")
+                .global(true)
                 .long("username")
                 .takes_value(true)
                 .short("u")
@@ -45,28 +66,181 @@ fn main() {
                 .short("q")
                 .help("Disable the terminal bell sound"),
         )
+        .arg(Arg::with_name("theme").long("theme").takes_value(true).help(
+            "Choose which theme should termchat use: dark, light, \
+                    or the name of a theme file in $ConfigDir/termchat/themes",
+        ))
+        .arg(Arg::with_name("plain").long("plain").help(
+            "Print each message in one line and read the input by lines, \
+                    for dumb terminals and screen readers. Used by default if TERM=dumb",
+        ))
         .arg(
-            Arg::with_name("theme")
-                .long("theme")
-                .validator(|theme| match theme.to_lowercase().as_str() {
-                    "dark" | "light" => Ok(()),
-                    _ => Err("Theme accepts only dark and light as value".into()),
-                })
+            Arg::with_name("config")
+                .global(true)
+                .long("config")
                 .takes_value(true)
-                .help("Choose which theme should termchat use, values are dark and light"),
+                .value_name("path")
+                .help("Config file used instead of $ConfigDir/termchat/config"),
+        )
+        .arg(
+            Arg::with_name("check-config")
+                .long("check-config")
+                .help("Check the config file, reporting its unknown or invalid keys, and exit"),
+        )
+        .arg(
+            Arg::with_name("print-default-config")
+                .long("print-default-config")
+                .help("Print a config file with the default values and exit"),
+        )
+        .subcommand(
+            SubCommand::with_name("send")
+                .about("Send a message to the users of the LAN and exit")
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("user|all")
+                        .default_value("all")
+                        .help("User receiving the message, or all the users"),
+                )
+                .arg(wait_arg())
+                .arg(Arg::with_name("message").required(true).help("Message to send")),
+        )
+        .subcommand(
+            SubCommand::with_name("send-file")
+                .about("Send a file to all the users of the LAN and exit, once it is sent")
+                .arg(wait_arg())
+                .arg(Arg::with_name("path").required(true).help("Path of the file to send")),
+        )
+        .subcommand(
+            SubCommand::with_name("listen")
+                .about("Write the messages and events of the LAN chat to stdout until killed")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Text lines, or one json object per line"),
+                ),
         )
         .get_matches();
 
-    // The next unwraps are safe because we specified a default value and a validator
-    let config = Config::from_matches(matches);
+    if matches.is_present("print-default-config") {
+        return match Config::default_toml() {
+            Ok(content) => print!("{}", content),
+            Err(e) => exit_with_error(e),
+        }
+    }
 
-    let result = match Application::new(&config) {
-        Ok(mut app) => app.run(std::io::stdout()),
-        Err(e) => Err(e),
+    if matches.is_present("check-config") {
+        return match Config::check_file(matches.value_of("config").map(Path::new)) {
+            Ok(problems) if problems.is_empty() => println!("The config file is valid"),
+            Ok(problems) => {
+                problems.iter().for_each(|problem| eprintln!("{}", problem));
+                std::process::exit(EXIT_ERROR);
+            }
+            Err(e) => exit_with_error(e),
+        }
+    }
+
+    let mode = match matches.subcommand() {
+        ("send", Some(send)) => Mode::Send {
+            // Safe unwraps, the values are required or have a default
+            wait: wait_value(send),
+            to: Some(send.value_of("to").unwrap()).filter(|to| *to != "all").map(String::from),
+            message: send.value_of("message").unwrap().into(),
+        },
+        ("send-file", Some(send_file)) => Mode::SendFile {
+            wait: wait_value(send_file),
+            path: send_file.value_of("path").unwrap().into(),
+        },
+        ("listen", Some(listen)) => Mode::Listen(match listen.value_of("format") {
+            Some("json") => Format::Json,
+            _ => Format::Text,
+        }),
+        _ => Mode::Chat {
+            plain: matches.is_present("plain")
+                || std::env::var("TERM").is_ok_and(|term| term == "dumb"),
+        },
+    };
+    let config = match Config::from_matches(matches) {
+        Ok(config) => config,
+        Err(e) => return eprintln!("termchat config error: {}", e),
     };
 
+    let plain = match mode {
+        Mode::Chat { plain } => plain,
+        Mode::Send { wait, to, message } => {
+            let result = oneshot::send_message(node_config(&config), wait, to.as_deref(), &message);
+            exit_oneshot(result, to.as_deref())
+        }
+        Mode::SendFile { wait, path } => {
+            exit_oneshot(oneshot::send_file(node_config(&config), wait, &path), None)
+        }
+        Mode::Listen(format) => {
+            if let Err(e) = listen::listen(node_config(&config), format, std::io::stdout()) {
+                eprintln!("termchat exited with error: {}", e);
+                std::process::exit(EXIT_ERROR);
+            }
+            return
+        }
+    };
+
+    let result = Application::new(config).and_then(|mut app| match plain {
+        true => app.run(PlainFrontend::new(std::io::stdout())),
+        false => app.run(TuiFrontend::new(std::io::stdout())?),
+    });
+
     if let Err(e) = result {
         // app is now dropped we can print to stderr safely
         eprintln!("termchat exited with error: {}", e);
     }
 }
+
+/// Time that the commands sending to the LAN wait for the users to connect
+fn wait_arg() -> Arg<'static, 'static> {
+    Arg::with_name("wait")
+        .long("wait")
+        .takes_value(true)
+        .value_name("secs")
+        .default_value("1")
+        .validator(|secs| match secs.parse::<f64>().map(Duration::try_from_secs_f64) {
+            Ok(Ok(_)) => Ok(()),
+            _ => Err("The value must be a positive number of seconds".into()),
+        })
+        .help("Seconds waiting for the users of the LAN to connect")
+}
+
+fn wait_value(matches: &clap::ArgMatches) -> Duration {
+    // Safe unwraps, the value has a default and was validated
+    Duration::from_secs_f64(matches.value_of("wait").unwrap().parse().unwrap())
+}
+
+/// The modes without interface report the config warnings in the standard error
+fn node_config(config: &Config) -> NodeConfig {
+    config.warnings.iter().for_each(|warning| eprintln!("{}", warning));
+    NodeConfig::from(config)
+}
+
+fn exit_oneshot(result: Result<Vec<Peer>, impl std::fmt::Display>, to: Option<&str>) -> ! {
+    match result {
+        Ok(peers) if peers.is_empty() => {
+            match to {
+                Some(user) => eprintln!("termchat: user '{}' not found in the LAN", user),
+                None => eprintln!("termchat: no users found in the LAN"),
+            }
+            std::process::exit(EXIT_NO_USERS)
+        }
+        Ok(_) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("termchat exited with error: {}", e);
+            std::process::exit(EXIT_ERROR)
+        }
+    }
+}
+
+fn exit_with_error(error: impl std::fmt::Display) -> ! {
+    eprintln!("termchat config error: {}", error);
+    std::process::exit(EXIT_ERROR);
+}
@@ -8,6 +8,14 @@ pub enum Chunk {
     End,
 }
 
+/// Screen of a terminal shared by `?share-term`
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TermScreen {
+    Contents(String, u16, u16, Vec<u8>), // command, rows, cols, formatted screen contents
+    Diff(Vec<u8>),                       // changes since the previous screen
+    End,
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum NetMessage {
     HelloLan(String, u16),                     // user_name, server_port
@@ -15,4 +23,6 @@ pub enum NetMessage {
     UserMessage(String),                       // content
     UserData(String, Chunk),                   // file_name, chunk
     Stream(Option<(Vec<RGB8>, usize, usize)>), // Option of (stream_data width, height ) None means stream has ended
+    TermScreen(TermScreen),
+    TailLines(String, Vec<String>, usize), // file_name, lines, skipped lines
 }
@@ -0,0 +1,451 @@
+use crate::message::{NetMessage, Chunk};
+use crate::encoder::{self, Encoder};
+use crate::util::{Result};
+
+pub use crate::message::{TermScreen};
+
+use message_io::network::{Endpoint, NetEvent, SendStatus, Transport};
+use message_io::node::{self, NodeEvent, NodeHandler, NodeTask};
+
+use rgb::RGB8;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration};
+
+/// Settings of a [`ChatNode`]
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    pub user_name: String,
+    /// Multicast address where the other nodes are found
+    pub discovery_addr: SocketAddrV4,
+    /// Port listening to the other nodes, 0 to choose any free port
+    pub tcp_server_port: u16,
+    /// The received files are saved in `<download_dir>/<user>/<file_name>`
+    pub download_dir: PathBuf,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            user_name: whoami::username(),
+            discovery_addr: "238.255.0.1:5877".parse().unwrap(),
+            tcp_server_port: 0,
+            download_dir: std::env::temp_dir().join("termchat"),
+        }
+    }
+}
+
+/// Other termchat user connected to this node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Peer {
+    pub name: String,
+    pub endpoint: Endpoint,
+}
+
+/// A file being sent by [`ChatNode::send_file()`]
+#[derive(Clone, Debug)]
+pub struct FileTransfer {
+    pub id: usize,
+    pub file_name: String,
+    pub size: u64,
+}
+
+/// What happens in the chat, notified by the [`ChatNode`]
+#[derive(Debug)]
+pub enum ChatEvent {
+    Connected(Peer),
+    Disconnected(Peer),
+    Message(Peer, String),
+    FileReceiving(Peer, String),    // file_name, its first chunk arrived
+    FileReceived(Peer, PathBuf),    // saved file path
+    FileReceiveError(Peer, String), // file_name
+    FileProgress(usize, u64),       // transfer id, bytes sent since the last progress
+    FileSent(usize),                // transfer id
+    FileSendError(usize, String),   // transfer id, error
+    Stream(Peer, Option<(Vec<RGB8>, usize, usize)>), // frame, width, height. None when it ends
+    TermScreen(Peer, TermScreen),
+    TailLines(Peer, String, Vec<String>, usize), // file_name, lines, skipped lines
+    Error(String),
+}
+
+enum NodeSignal {
+    SendFile(FileSending),
+}
+
+struct FileSending {
+    id: usize,
+    file_name: String,
+    file: File,
+}
+
+type Peers = Arc<Mutex<HashMap<Endpoint, String>>>;
+
+/// Core of termchat: finds the other users in the LAN and chats with them,
+/// without any user interface.
+///
+/// The network is processed in its own thread since the node is created until it is dropped.
+/// ```no_run
+/// use termchat::node::{ChatNode, ChatEvent, NodeConfig};
+///
+/// let node = ChatNode::new(NodeConfig::default()).unwrap();
+/// node.send_message("Hello everyone!");
+/// for event in node.events().unwrap() {
+///     if let ChatEvent::Message(peer, content) = event {
+///         println!("{}: {}", peer.name, content);
+///     }
+/// }
+/// ```
+pub struct ChatNode {
+    user_name: String,
+    server_addr: SocketAddr,
+    discovery_addr: SocketAddrV4,
+    handler: NodeHandler<NodeSignal>,
+    task: Option<NodeTask>,
+    peers: Peers,
+    encoder: Mutex<Encoder>,
+    last_transfer_id: AtomicUsize,
+    // None if the events are given to a callback
+    events: Option<Receiver<ChatEvent>>,
+}
+
+impl ChatNode {
+    /// Creates a node whose events are read from [`ChatNode::events()`]
+    pub fn new(config: NodeConfig) -> Result<ChatNode> {
+        let (sender, events) = mpsc::channel();
+        Self::start(config, Some(events), move |event| {
+            // Nobody is reading the events if the receiver was dropped
+            let _ = sender.send(event);
+        })
+    }
+
+    /// Creates a node that notifies its events calling the callback from the network thread.
+    /// In this case there is no [`ChatNode::events()`] receiver.
+    pub fn with_callback(
+        config: NodeConfig,
+        callback: impl FnMut(ChatEvent) + Send + 'static,
+    ) -> Result<ChatNode> {
+        Self::start(config, None, callback)
+    }
+
+    fn start(
+        config: NodeConfig,
+        events: Option<Receiver<ChatEvent>>,
+        callback: impl FnMut(ChatEvent) + Send + 'static,
+    ) -> Result<ChatNode> {
+        let (handler, listener) = node::split();
+
+        let server_addr = ("0.0.0.0", config.tcp_server_port);
+        let (_, server_addr) = handler.network().listen(Transport::FramedTcp, server_addr)?;
+        handler.network().listen(Transport::Udp, config.discovery_addr)?;
+
+        let peers = Peers::default();
+        let mut processor = NodeProcessor {
+            user_name: config.user_name.clone(),
+            download_dir: config.download_dir,
+            handler: handler.clone(),
+            peers: peers.clone(),
+            connecting: HashMap::new(),
+            receiving_files: HashSet::new(),
+            encoder: Encoder::new(),
+            callback: Box::new(callback),
+        };
+        let task = listener.for_each_async(move |event| processor.process(event));
+
+        let node = ChatNode {
+            user_name: config.user_name,
+            server_addr,
+            discovery_addr: config.discovery_addr,
+            handler,
+            task: Some(task),
+            peers,
+            encoder: Mutex::new(Encoder::new()),
+            last_transfer_id: AtomicUsize::new(0),
+            events,
+        };
+
+        let (discovery_endpoint, _) =
+            node.handler.network().connect_sync(Transport::Udp, config.discovery_addr)?;
+        let message = NetMessage::HelloLan(node.user_name.clone(), server_addr.port());
+        node.send(&[discovery_endpoint], message);
+
+        Ok(node)
+    }
+
+    pub fn user_name(&self) -> &str {
+        &self.user_name
+    }
+
+    /// Address listening to the other nodes
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server_addr
+    }
+
+    pub fn discovery_addr(&self) -> SocketAddrV4 {
+        self.discovery_addr
+    }
+
+    /// Events of the chat, given while the node lives.
+    /// None if the node was created with [`ChatNode::with_callback()`].
+    pub fn events(&self) -> Option<&Receiver<ChatEvent>> {
+        self.events.as_ref()
+    }
+
+    /// The connected users, sorted by name
+    pub fn peers(&self) -> Vec<Peer> {
+        let mut peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(endpoint, name)| Peer { name: name.clone(), endpoint: *endpoint })
+            .collect::<Vec<_>>();
+        peers.sort_by(|a, b| a.name.cmp(&b.name));
+        peers
+    }
+
+    /// Sends a text message to all the users
+    pub fn send_message(&self, content: &str) {
+        self.send_all(NetMessage::UserMessage(content.into()));
+    }
+
+    /// Sends a text message only to one user.
+    /// Fails if the message could not be sent, as when the user has just disconnected.
+    pub fn send_message_to(&self, peer: &Peer, content: &str) -> Result<()> {
+        let message = NetMessage::UserMessage(content.into());
+        match self.send(&[peer.endpoint], message).as_slice() {
+            [(_, SendStatus::Sent)] => Ok(()),
+            [(_, status)] => {
+                Err(format!("Unable to send the message to '{}': {:?}", peer.name, status).into())
+            }
+            _ => unreachable!("One status by endpoint"),
+        }
+    }
+
+    /// Sends a file to all the users, in the background.
+    /// Its progress is notified by [`ChatEvent::FileProgress`] until [`ChatEvent::FileSent`]
+    pub fn send_file(&self, file_path: &Path) -> Result<FileTransfer> {
+        const READ_FILENAME_ERROR: &str = "Unable to read file name";
+        let file_name = file_path
+            .file_name()
+            .ok_or(READ_FILENAME_ERROR)?
+            .to_str()
+            .ok_or(READ_FILENAME_ERROR)?
+            .to_string();
+
+        let size = std::fs::metadata(file_path)?.len();
+        let file = File::open(file_path)?;
+
+        let id = self.last_transfer_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let sending = FileSending { id, file_name: file_name.clone(), file };
+        self.handler.signals().send(NodeSignal::SendFile(sending));
+        Ok(FileTransfer { id, file_name, size })
+    }
+
+    /// Sends any message of the termchat protocol to all the users
+    pub(crate) fn send_all(&self, message: NetMessage) {
+        let endpoints = self.peers.lock().unwrap().keys().copied().collect::<Vec<_>>();
+        self.send(&endpoints, message);
+    }
+
+    /// Returns how the message was sent to each endpoint
+    fn send(&self, endpoints: &[Endpoint], message: NetMessage) -> Vec<(Endpoint, SendStatus)> {
+        let mut encoder = self.encoder.lock().unwrap();
+        let data = encoder.encode(message);
+        endpoints
+            .iter()
+            .map(|endpoint| (*endpoint, self.handler.network().send(*endpoint, data)))
+            .collect()
+    }
+}
+
+impl Drop for ChatNode {
+    fn drop(&mut self) {
+        self.handler.stop();
+        // Waits the network thread to finish
+        self.task.take();
+    }
+}
+
+/// Processes the events of the network thread
+struct NodeProcessor {
+    user_name: String,
+    download_dir: PathBuf,
+    handler: NodeHandler<NodeSignal>,
+    peers: Peers,
+    // Users found by multicast whose connection is not established yet
+    connecting: HashMap<Endpoint, String>,
+    receiving_files: HashSet<(Endpoint, String)>,
+    encoder: Encoder,
+    callback: Box<dyn FnMut(ChatEvent) + Send>,
+}
+
+impl NodeProcessor {
+    // We add a minor delay to introduce a rate in the sending.
+    const CHUNK_DELAY: Duration = Duration::from_micros(100);
+    const CHUNK_SIZE: usize = 32768;
+
+    fn process(&mut self, event: NodeEvent<NodeSignal>) {
+        match event {
+            NodeEvent::Network(net_event) => match net_event {
+                NetEvent::Connected(endpoint, established) => {
+                    if let Some(user) = self.connecting.remove(&endpoint) {
+                        match established {
+                            true => {
+                                let message = NetMessage::HelloUser(self.user_name.clone());
+                                self.handler.network().send(endpoint, self.encoder.encode(message));
+                                self.connected(endpoint, user);
+                            }
+                            false => self.notify(ChatEvent::Error(format!(
+                                "Unable to connect to '{}' at {}",
+                                user,
+                                endpoint.addr()
+                            ))),
+                        }
+                    }
+                }
+                NetEvent::Accepted(_endpoint, _resource_id) => (),
+                NetEvent::Message(endpoint, data) => match encoder::decode(data) {
+                    Some(message) => self.process_network_message(endpoint, message),
+                    None => self.notify(ChatEvent::Error("Unknown message received".into())),
+                },
+                NetEvent::Disconnected(endpoint) => {
+                    self.receiving_files.retain(|(receiving, _)| *receiving != endpoint);
+                    let name = self.peers.lock().unwrap().remove(&endpoint);
+                    if let Some(name) = name {
+                        self.notify(ChatEvent::Disconnected(Peer { name, endpoint }));
+                    }
+                }
+            },
+            NodeEvent::Signal(signal) => match signal {
+                NodeSignal::SendFile(sending) => self.send_file_chunk(sending),
+            },
+        }
+    }
+
+    fn process_network_message(&mut self, endpoint: Endpoint, message: NetMessage) {
+        match message {
+            // by udp (multicast):
+            NetMessage::HelloLan(user, server_port) => {
+                if user != self.user_name {
+                    let server_addr = (endpoint.addr().ip(), server_port);
+                    match self.handler.network().connect(Transport::FramedTcp, server_addr) {
+                        Ok((user_endpoint, _)) => {
+                            self.connecting.insert(user_endpoint, user);
+                        }
+                        Err(e) => self.notify(ChatEvent::Error(e.to_string())),
+                    }
+                }
+            }
+            // by tcp:
+            NetMessage::HelloUser(user) => self.connected(endpoint, user),
+            message => {
+                let name = self.peers.lock().unwrap().get(&endpoint).cloned();
+                // Only the known users can send messages
+                if let Some(name) = name {
+                    if let Some(event) = self.peer_event(Peer { name, endpoint }, message) {
+                        self.notify(event);
+                    }
+                }
+            }
+        }
+    }
+
+    fn peer_event(&mut self, peer: Peer, message: NetMessage) -> Option<ChatEvent> {
+        Some(match message {
+            NetMessage::HelloLan(..) | NetMessage::HelloUser(..) => return None,
+            NetMessage::UserMessage(content) => ChatEvent::Message(peer, content),
+            NetMessage::UserData(file_name, chunk) => {
+                return self.receive_file(&peer, file_name, chunk)
+            }
+            NetMessage::Stream(frame) => ChatEvent::Stream(peer, frame),
+            NetMessage::TermScreen(screen) => ChatEvent::TermScreen(peer, screen),
+            NetMessage::TailLines(file_name, lines, skipped_lines) => {
+                ChatEvent::TailLines(peer, file_name, lines, skipped_lines)
+            }
+        })
+    }
+
+    fn connected(&mut self, endpoint: Endpoint, name: String) {
+        self.peers.lock().unwrap().insert(endpoint, name.clone());
+        self.notify(ChatEvent::Connected(Peer { name, endpoint }));
+    }
+
+    fn receive_file(&mut self, peer: &Peer, file_name: String, chunk: Chunk) -> Option<ChatEvent> {
+        // Only the name is used, the file can not be saved out of the user directory
+        let file_name = Path::new(&file_name).file_name()?.to_str()?.to_string();
+        let user_path = self.download_dir.join(&peer.name);
+        let file_path = user_path.join(&file_name);
+        let key = (peer.endpoint, file_name);
+
+        match chunk {
+            Chunk::Error => {
+                self.receiving_files.remove(&key);
+                Some(ChatEvent::FileReceiveError(peer.clone(), key.1))
+            }
+            Chunk::End => {
+                self.receiving_files.remove(&key);
+                Some(ChatEvent::FileReceived(peer.clone(), file_path))
+            }
+            Chunk::Data(data) => {
+                let first_chunk = self.receiving_files.insert(key.clone());
+                let try_write = || -> Result<()> {
+                    std::fs::create_dir_all(&user_path)?;
+                    // A previous file with the same name is replaced
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .append(!first_chunk)
+                        .truncate(first_chunk)
+                        .open(&file_path)?
+                        .write_all(&data)?;
+                    Ok(())
+                };
+
+                match try_write() {
+                    Err(e) => Some(ChatEvent::Error(format!(
+                        "Unable to save '{}' from '{}': {}",
+                        key.1, peer.name, e
+                    ))),
+                    Ok(()) if first_chunk => Some(ChatEvent::FileReceiving(peer.clone(), key.1)),
+                    Ok(()) => None,
+                }
+            }
+        }
+    }
+
+    fn send_file_chunk(&mut self, mut sending: FileSending) {
+        let mut data = [0; Self::CHUNK_SIZE];
+        let (chunk, event) = match sending.file.read(&mut data) {
+            Ok(0) => (Chunk::End, ChatEvent::FileSent(sending.id)),
+            Ok(bytes_read) => {
+                let event = ChatEvent::FileProgress(sending.id, bytes_read as u64);
+                (Chunk::Data(data[..bytes_read].to_vec()), event)
+            }
+            Err(e) => (Chunk::Error, ChatEvent::FileSendError(sending.id, e.to_string())),
+        };
+
+        let keep_sending = matches!(chunk, Chunk::Data(_));
+        let message = self.encoder.encode(NetMessage::UserData(sending.file_name.clone(), chunk));
+        for endpoint in self.peers.lock().unwrap().keys() {
+            self.handler.network().send(*endpoint, message);
+        }
+        self.notify(event);
+
+        if keep_sending {
+            self.handler
+                .signals()
+                .send_with_timer(NodeSignal::SendFile(sending), Self::CHUNK_DELAY);
+        }
+    }
+
+    fn notify(&mut self, event: ChatEvent) {
+        (self.callback)(event)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::node::{ChatNode, ChatEvent, NodeConfig, Peer};
+use crate::util::{Result};
+
+use std::path::{Path};
+use std::time::{Duration, Instant};
+
+/// Joins the chat to send a message and leaves it, to be used from scripts.
+/// The users of the LAN are waited to connect during `wait`.
+/// Returns the users that received the message: all, or the ones with the `to` name.
+/// Fails if the message could not be sent to any of them.
+pub fn send_message(
+    config: NodeConfig,
+    wait: Duration,
+    to: Option<&str>,
+    content: &str,
+) -> Result<Vec<Peer>> {
+    let node = ChatNode::new(config)?;
+    let peers = discover(&node, wait, to);
+    let errors = peers
+        .iter()
+        .filter_map(|peer| node.send_message_to(peer, content).err())
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>();
+    match errors.is_empty() {
+        true => Ok(peers),
+        false => Err(errors.join("\n").into()),
+    }
+}
+
+/// Joins the chat to send a file to all the users, leaving it when the file was sent.
+/// The users of the LAN are waited to connect during `wait`.
+/// Returns the users that received the file.
+pub fn send_file(config: NodeConfig, wait: Duration, file_path: &Path) -> Result<Vec<Peer>> {
+    // Checked before waiting the users
+    std::fs::File::open(file_path).map_err(|e| format!("'{}': {}", file_path.display(), e))?;
+    let node = ChatNode::new(config)?;
+    let peers = discover(&node, wait, None);
+    if peers.is_empty() {
+        return Ok(peers)
+    }
+
+    let transfer = node.send_file(file_path)?;
+    // Safe unwrap, the node was not created with a callback
+    for event in node.events().unwrap() {
+        match event {
+            ChatEvent::FileSent(id) if id == transfer.id => return Ok(peers),
+            ChatEvent::FileSendError(id, error) if id == transfer.id => return Err(error.into()),
+            _ => (),
+        }
+    }
+    Err("The node stopped before sending the file".into())
+}
+
+/// Waits the users to connect, until the user is found if only one is wanted
+fn discover(node: &ChatNode, wait: Duration, user: Option<&str>) -> Vec<Peer> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        // Safe unwrap, the node was not created with a callback
+        match node.events().unwrap().recv_timeout(timeout) {
+            Ok(ChatEvent::Connected(peer)) if Some(peer.name.as_str()) == user => break,
+            Ok(_) => (),
+            Err(_) => break, // Timeout
+        }
+    }
+    node.peers().into_iter().filter(|peer| user.is_none_or(|user| peer.name == user)).collect()
+}
@@ -1,8 +1,15 @@
+use crate::history::{InputHistory};
+use crate::message::{TermScreen};
+use crate::vi::{ViMode, ViState};
+use crate::util::{Reportable};
+
 use message_io::network::Endpoint;
 use chrono::{DateTime, Local};
 use rgb::RGB8;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
 
 #[derive(PartialEq, Eq)]
 pub enum SystemMessageType {
@@ -18,12 +25,73 @@ pub enum ProgressState {
     Completed,
 }
 
+/// Output of a shell command run by `?exec`, it grows while the command runs
+pub struct CommandOutput {
+    pub command: String,
+    pub lines: Vec<OutputLine>,
+    // Lines not kept to limit the size of the message
+    pub skipped_lines: usize,
+    // How the command finished, None while it is running
+    pub exit: Option<String>,
+}
+
+impl CommandOutput {
+    const MAX_LINES: usize = 500;
+
+    /// Output as plain text, as it is sent to other users
+    pub fn text(&self) -> String {
+        let mut text = format!("$ {}", self.command);
+        for line in &self.lines {
+            text.push('\n');
+            text.push_str(&line.content);
+        }
+        if self.skipped_lines > 0 {
+            text.push_str(&format!("\n[{} more lines]", self.skipped_lines));
+        }
+        if let Some(exit) = &self.exit {
+            text.push_str(&format!("\n[{}]", exit));
+        }
+        text
+    }
+}
+
+/// Lines appended to a file followed by `?tail`, sent each period
+pub struct TailLines {
+    pub file_name: String,
+    pub lines: Vec<String>,
+    // Lines not sent to limit the rate
+    pub skipped_lines: usize,
+}
+
+impl TailLines {
+    /// Lines as plain text, with the skipped ones noted
+    pub fn text(&self) -> String {
+        let mut text = format!("[{}]", self.file_name);
+        if self.skipped_lines > 0 {
+            text.push_str(&format!("\n[{} lines skipped]", self.skipped_lines));
+        }
+        for line in &self.lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text
+    }
+}
+
+pub struct OutputLine {
+    pub content: String,
+    pub error: bool, // written to stderr
+}
+
 pub enum MessageType {
     Connection,
     Disconnection,
     Text(String),
     System(String, SystemMessageType),
+    FileReceived(String, PathBuf), // user, file_path
     Progress(ProgressState),
+    CommandOutput(CommandOutput),
+    TailLines(TailLines),
 }
 
 pub struct ChatMessage {
@@ -36,6 +104,20 @@ impl ChatMessage {
     pub fn new(user: String, message_type: MessageType) -> ChatMessage {
         ChatMessage { date: Local::now(), user, message_type }
     }
+
+    /// Plain text of the message, used to copy or quote it
+    pub fn text(&self) -> String {
+        match &self.message_type {
+            MessageType::Connection => format!("{} is online", self.user),
+            MessageType::Disconnection => format!("{} is offline", self.user),
+            MessageType::Text(content) => content.clone(),
+            MessageType::System(content, _) => content.clone(),
+            MessageType::FileReceived(_, path) => path.display().to_string(),
+            MessageType::Progress(_) => String::new(),
+            MessageType::CommandOutput(output) => output.text(),
+            MessageType::TailLines(tail) => tail.text(),
+        }
+    }
 }
 
 pub struct Window {
@@ -48,49 +130,218 @@ impl Window {
         Self { data: vec![], width, height }
     }
 }
+/// A terminal shared by `?share-term`, with the screen rebuilt from its output
+pub struct SharedTerminal {
+    pub command: String,
+    pub parser: vt100::Parser,
+}
+
+impl SharedTerminal {
+    // Limits for the sizes received from other users
+    const MAX_ROWS: u16 = 200;
+    const MAX_COLS: u16 = 500;
+
+    pub fn new(command: &str, rows: u16, cols: u16) -> Self {
+        let (rows, cols) = (rows.min(Self::MAX_ROWS), cols.min(Self::MAX_COLS));
+        Self { command: command.into(), parser: vt100::Parser::new(rows, cols, 0) }
+    }
+}
+
+/// Reverse incremental search over the input history
+pub struct HistorySearch {
+    pub query: String,
+    found: Option<usize>,
+    original_input: Vec<char>,
+}
+
+impl HistorySearch {
+    pub fn is_found(&self) -> bool {
+        self.found.is_some()
+    }
+}
+
+/// Candidates of a tab completion being cycled
+pub struct InputCompletion {
+    start: usize,
+    end: usize,
+    candidates: Vec<String>,
+    selected: usize,
+}
+
+impl InputCompletion {
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+}
+
+/// Connection details shown in the status bar
+pub struct ConnectionInfo {
+    pub user_name: String,
+    pub server_addr: SocketAddr,
+    pub discovery_addr: SocketAddrV4,
+}
+
 #[derive(Default)]
 pub struct State {
     messages: Vec<ChatMessage>,
-    scroll_messages_view: usize,
+    // First line shown in the messages panel, None means that the view follows the last message
+    scroll_messages_view: Option<usize>,
+    // Lines of all the messages and of the panel, as they were drawn the last time
+    messages_view_lines: usize,
+    messages_view_height: usize,
+    unread_messages: usize,
     input: Vec<char>,
     input_cursor: usize,
+    history: InputHistory,
+    history_position: Option<usize>,
+    history_draft: Vec<char>,
+    history_search: Option<HistorySearch>,
+    completion: Option<InputCompletion>,
+    kill_ring: KillRing,
+    vi: Option<ViState>,
     lan_users: HashMap<Endpoint, String>,
     users_id: HashMap<String, usize>,
     last_user_id: usize,
+    // Known once the application is listening
+    connection: Option<ConnectionInfo>,
+    // Files being received, with the endpoint sending each one
+    receiving_files: HashSet<(Endpoint, String)>,
+    // Progress message of each file being sent, by its transfer id
+    sending_files: HashMap<usize, usize>,
     pub stop_stream: bool,
+    pub sending_stream: bool,
+    // Incremented to cancel the commands run by `?exec` until then
+    exec_generation: usize,
+    // Canonical paths of the files followed by `?tail`, with the id of each `?tail`
+    tailed_files: Vec<(PathBuf, usize)>,
+    last_tail_id: usize,
     pub windows: HashMap<Endpoint, Window>,
+    // Terminals shared by others, and the one shared by us
+    pub terminals: HashMap<Endpoint, SharedTerminal>,
+    pub own_terminal: Option<SharedTerminal>,
+    pub stop_term_share: bool,
+    video_panel_hidden: bool,
+    selected_message: Option<usize>,
 }
 
 pub enum CursorMovement {
     Left,
     Right,
+    WordLeft,
+    WordRight,
+    NextWord,
     Start,
     End,
 }
 
+pub enum KillMovement {
+    WordLeft,
+    WordRight,
+    NextWord,
+    Start,
+    End,
+    All,
+}
+
+/// Text removed from the input, it can be yanked back later
+#[derive(Default)]
+struct KillRing {
+    entries: Vec<String>,
+    // (cursor, input length) after the last kill, to join consecutive kills in the same entry
+    last_kill: Option<(usize, usize)>,
+    // (start, end, entry index) of the last yanked text, to replace it with older entries
+    last_yank: Option<(usize, usize, usize)>,
+}
+
+impl KillRing {
+    const MAX_ENTRIES: usize = 16;
+}
+
 pub enum ScrollMovement {
     Up,
     Down,
-    Start,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
 }
 
 impl State {
+    pub fn new(history: InputHistory) -> State {
+        State { history, ..Default::default() }
+    }
+
     pub fn messages(&self) -> &Vec<ChatMessage> {
         &self.messages
     }
 
-    pub fn scroll_messages_view(&self) -> usize {
+    pub fn selected_message(&self) -> Option<usize> {
+        self.selected_message
+    }
+
+    /// Selecting the already selected message unselects it
+    pub fn select_message(&mut self, index: Option<usize>) {
+        self.selected_message = match index == self.selected_message {
+            true => None,
+            false => index,
+        };
+    }
+
+    pub fn scroll_messages_view(&self) -> Option<usize> {
         self.scroll_messages_view
     }
 
+    /// Messages added while the view was not following the last message
+    pub fn unread_messages(&self) -> usize {
+        self.unread_messages
+    }
+
     pub fn input(&self) -> &[char] {
         &self.input
     }
 
+    pub fn history_search(&self) -> Option<&HistorySearch> {
+        self.history_search.as_ref()
+    }
+
+    /// The current mode, if the vi editing mode is enabled
+    pub fn vi_mode(&self) -> Option<ViMode> {
+        self.vi.as_ref().map(|vi| vi.mode)
+    }
+
+    pub fn vi_mut(&mut self) -> Option<&mut ViState> {
+        self.vi.as_mut()
+    }
+
+    pub fn enable_vi_mode(&mut self, enabled: bool) {
+        self.vi = match enabled {
+            true => Some(self.vi.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    pub fn completion(&self) -> Option<&InputCompletion> {
+        self.completion.as_ref()
+    }
+
+    pub fn input_before_cursor(&self) -> &[char] {
+        &self.input[..self.input_cursor]
+    }
+
     pub fn ui_input_cursor(&self, width: usize) -> (u16, u16) {
         let mut position = (0, 0);
 
         for current_char in self.input.iter().take(self.input_cursor) {
+            if *current_char == '\n' {
+                position.0 = 0;
+                position.1 += 1;
+                continue
+            }
+
             let char_width = unicode_width::UnicodeWidthChar::width(*current_char).unwrap_or(0);
 
             position.0 += char_width;
@@ -117,6 +368,10 @@ impl State {
         self.lan_users.get(&endpoint)
     }
 
+    pub fn user_names(&self) -> impl Iterator<Item = &String> {
+        self.lan_users.values()
+    }
+
     pub fn all_user_endpoints(&self) -> impl Iterator<Item = &Endpoint> {
         self.lan_users.keys()
     }
@@ -135,6 +390,7 @@ impl State {
     }
 
     pub fn disconnected_user(&mut self, endpoint: Endpoint) {
+        self.receiving_files.retain(|(sender, _)| *sender != endpoint);
         if self.lan_users.contains_key(&endpoint) {
             // unwrap is safe because of the check above
             let user = self.lan_users.remove(&endpoint).unwrap();
@@ -143,17 +399,23 @@ impl State {
     }
 
     pub fn input_write(&mut self, character: char) {
+        self.history_position = None;
+        self.kill_ring.last_kill = None;
         self.input.insert(self.input_cursor, character);
         self.input_cursor += 1;
     }
 
     pub fn input_remove(&mut self) {
+        self.history_position = None;
+        self.kill_ring.last_kill = None;
         if self.input_cursor < self.input.len() {
             self.input.remove(self.input_cursor);
         }
     }
 
     pub fn input_remove_previous(&mut self) {
+        self.history_position = None;
+        self.kill_ring.last_kill = None;
         if self.input_cursor > 0 {
             self.input_cursor -= 1;
             self.input.remove(self.input_cursor);
@@ -161,6 +423,8 @@ impl State {
     }
 
     pub fn input_move_cursor(&mut self, movement: CursorMovement) {
+        // Only consecutive kills are joined
+        self.kill_ring.last_kill = None;
         match movement {
             CursorMovement::Left => {
                 if self.input_cursor > 0 {
@@ -172,6 +436,15 @@ impl State {
                     self.input_cursor += 1;
                 }
             }
+            CursorMovement::WordLeft => {
+                self.input_cursor = self.input_word_left();
+            }
+            CursorMovement::WordRight => {
+                self.input_cursor = self.input_word_right();
+            }
+            CursorMovement::NextWord => {
+                self.input_cursor = self.input_next_word();
+            }
             CursorMovement::Start => {
                 self.input_cursor = 0;
             }
@@ -181,50 +454,409 @@ impl State {
         }
     }
 
-    pub fn messages_scroll(&mut self, movement: ScrollMovement) {
-        match movement {
-            ScrollMovement::Up => {
-                if self.scroll_messages_view > 0 {
-                    self.scroll_messages_view -= 1;
+    /// Start of the word at the left of the cursor, words are made of alphanumeric chars
+    fn input_word_left(&self) -> usize {
+        let before = &self.input[..self.input_cursor];
+        let word_end = before.iter().rposition(|c| c.is_alphanumeric()).map_or(0, |i| i + 1);
+        before[..word_end].iter().rposition(|c| !c.is_alphanumeric()).map_or(0, |i| i + 1)
+    }
+
+    /// End of the word at the right of the cursor, words are made of alphanumeric chars
+    fn input_word_right(&self) -> usize {
+        let after = &self.input[self.input_cursor..];
+        let word_start = after.iter().position(|c| c.is_alphanumeric()).unwrap_or(after.len());
+        let word_end = after[word_start..]
+            .iter()
+            .position(|c| !c.is_alphanumeric())
+            .map_or(after.len(), |i| word_start + i);
+        self.input_cursor + word_end
+    }
+
+    /// Start of the next word after the cursor, words are made of alphanumeric chars
+    fn input_next_word(&self) -> usize {
+        let after = &self.input[self.input_cursor..];
+        let word_end = after.iter().position(|c| !c.is_alphanumeric()).unwrap_or(after.len());
+        let next_start = after[word_end..]
+            .iter()
+            .position(|c| c.is_alphanumeric())
+            .map_or(after.len(), |i| word_end + i);
+        self.input_cursor + next_start
+    }
+
+    /// Remove text from the input storing it in the kill ring.
+    /// Consecutive kills are joined in the same kill ring entry.
+    pub fn input_kill(&mut self, movement: KillMovement) {
+        let (start, end) = match movement {
+            KillMovement::WordLeft => {
+                // Like a shell does, a word here is everything until a whitespace
+                let before = &self.input[..self.input_cursor];
+                let word_end = before.iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1);
+                let start =
+                    before[..word_end].iter().rposition(|c| c.is_whitespace()).map_or(0, |i| i + 1);
+                (start, self.input_cursor)
+            }
+            KillMovement::WordRight => (self.input_cursor, self.input_word_right()),
+            KillMovement::NextWord => (self.input_cursor, self.input_next_word()),
+            KillMovement::Start => (0, self.input_cursor),
+            KillMovement::End => (self.input_cursor, self.input.len()),
+            KillMovement::All => (0, self.input.len()),
+        };
+
+        if start == end {
+            return
+        }
+
+        self.history_position = None;
+        let before_kill = (self.input_cursor, self.input.len());
+        let killed: String = self.input.drain(start..end).collect();
+        self.input_cursor = start;
+
+        let ring = &mut self.kill_ring;
+        match ring.entries.last_mut() {
+            Some(last) if ring.last_kill == Some(before_kill) => match movement {
+                KillMovement::WordLeft | KillMovement::Start => last.insert_str(0, &killed),
+                _ => last.push_str(&killed),
+            },
+            _ => {
+                ring.entries.push(killed);
+                if ring.entries.len() > KillRing::MAX_ENTRIES {
+                    ring.entries.remove(0);
                 }
             }
-            ScrollMovement::Down => {
-                self.scroll_messages_view += 1;
+        }
+        ring.last_kill = Some((self.input_cursor, self.input.len()));
+        ring.last_yank = None;
+    }
+
+    /// Insert the last killed text at the cursor position
+    pub fn input_yank(&mut self) {
+        if let Some(index) = self.kill_ring.entries.len().checked_sub(1) {
+            self.input_yank_entry(self.input_cursor, self.input_cursor, index);
+        }
+    }
+
+    /// Just after a yank, replace the yanked text by the previous kill ring entry
+    pub fn input_yank_pop(&mut self) {
+        if let Some((start, end, index)) = self.kill_ring.last_yank {
+            if end == self.input_cursor {
+                let len = self.kill_ring.entries.len();
+                self.input_yank_entry(start, end, (index + len - 1) % len);
+            }
+        }
+    }
+
+    fn input_yank_entry(&mut self, start: usize, end: usize, index: usize) {
+        self.history_position = None;
+        let text = self.kill_ring.entries[index].clone();
+        let end = self.input_replace(start, end, &text);
+        self.kill_ring.last_yank = Some((start, end, index));
+    }
+
+    pub fn messages_scroll(&mut self, movement: ScrollMovement) {
+        let last_top = self.messages_view_lines.saturating_sub(self.messages_view_height);
+        let page = self.messages_view_height.saturating_sub(1).max(1);
+        let top = self.scroll_messages_view.unwrap_or(last_top).min(last_top);
+
+        let top = match movement {
+            ScrollMovement::Up => top.saturating_sub(1),
+            ScrollMovement::Down => top + 1,
+            ScrollMovement::PageUp => top.saturating_sub(page),
+            ScrollMovement::PageDown => top + page,
+            ScrollMovement::Top => 0,
+            ScrollMovement::Bottom => last_top,
+        };
+        self.scroll_messages_view = Some(top);
+        self.messages_stick_to_bottom();
+    }
+
+    /// Called after drawing the messages, the wrapped lines depend on the panel size
+    pub fn update_messages_view(&mut self, lines: usize, height: usize) {
+        self.messages_view_lines = lines;
+        self.messages_view_height = height;
+        self.messages_stick_to_bottom();
+    }
+
+    /// Once the view reaches the last message, it follows the new ones
+    fn messages_stick_to_bottom(&mut self) {
+        let last_top = self.messages_view_lines.saturating_sub(self.messages_view_height);
+        if self.scroll_messages_view.is_some_and(|top| top >= last_top) {
+            self.scroll_messages_view = None;
+            self.unread_messages = 0;
+        }
+    }
+
+    /// Replace the word being completed by the first candidate.
+    /// If there are more candidates, they can be cycled with `input_completion_cycle()`.
+    pub fn input_complete(&mut self, start: usize, candidates: Vec<String>) {
+        self.history_position = None;
+        let end = self.input_replace(start, self.input_cursor, &candidates[0]);
+        if candidates.len() > 1 {
+            self.completion = Some(InputCompletion { start, end, candidates, selected: 0 });
+        }
+    }
+
+    /// Returns false if there was no completion to cycle
+    pub fn input_completion_cycle(&mut self, forward: bool) -> bool {
+        if let Some(mut completion) = self.completion.take() {
+            let len = completion.candidates.len();
+            completion.selected = match forward {
+                true => (completion.selected + 1) % len,
+                false => (completion.selected + len - 1) % len,
+            };
+            let candidate = completion.candidates[completion.selected].clone();
+            completion.end = self.input_replace(completion.start, completion.end, &candidate);
+            self.completion = Some(completion);
+            return true
+        }
+        false
+    }
+
+    pub fn input_completion_end(&mut self) {
+        self.completion = None;
+    }
+
+    /// Replace the input in the range by the text, leaving the cursor after it.
+    /// Returns the new end of the range.
+    fn input_replace(&mut self, start: usize, end: usize, text: &str) -> usize {
+        self.kill_ring.last_kill = None;
+        self.input.splice(start..end, text.chars());
+        self.input_cursor = start + text.chars().count();
+        self.input_cursor
+    }
+
+    /// Insert the text at the cursor position
+    pub fn input_insert(&mut self, text: &str) {
+        self.history_position = None;
+        self.input_replace(self.input_cursor, self.input_cursor, text);
+    }
+
+    fn set_input(&mut self, input: Vec<char>) {
+        self.kill_ring.last_kill = None;
+        self.input = input;
+        self.input_cursor = self.input.len();
+    }
+
+    fn history_entry(&self, index: usize) -> Vec<char> {
+        self.history.get(index).unwrap_or_default().chars().collect()
+    }
+
+    pub fn input_history_previous(&mut self) {
+        let index = match self.history_position {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.history_draft = std::mem::take(&mut self.input);
+                self.history.len() - 1
+            }
+        };
+        self.history_position = Some(index);
+        self.set_input(self.history_entry(index));
+    }
+
+    pub fn input_history_next(&mut self) {
+        if let Some(index) = self.history_position {
+            if index + 1 < self.history.len() {
+                self.history_position = Some(index + 1);
+                self.set_input(self.history_entry(index + 1));
             }
-            ScrollMovement::Start => {
-                self.scroll_messages_view += 0;
+            else {
+                // Back to the line that was being written before browsing
+                self.history_position = None;
+                let draft = std::mem::take(&mut self.history_draft);
+                self.set_input(draft);
             }
         }
     }
 
+    pub fn history_search_start(&mut self) {
+        match self.history_search.take() {
+            Some(mut search) => {
+                // Pressing it again looks for an older match
+                if let Some(found) = search.found {
+                    if let Some(index) = self.history_find(&search.query, found) {
+                        search.found = Some(index);
+                    }
+                }
+                self.history_search_show(search);
+            }
+            None => {
+                self.history_position = None;
+                self.history_search = Some(HistorySearch {
+                    query: String::new(),
+                    found: None,
+                    original_input: self.input.clone(),
+                });
+            }
+        }
+    }
+
+    pub fn history_search_write(&mut self, character: char) {
+        if let Some(mut search) = self.history_search.take() {
+            search.query.push(character);
+            // The current match could still match the longer query
+            let before = search.found.map(|index| index + 1).unwrap_or_else(|| self.history.len());
+            search.found = self.history_find(&search.query, before);
+            self.history_search_show(search);
+        }
+    }
+
+    pub fn history_search_remove_previous(&mut self) {
+        if let Some(mut search) = self.history_search.take() {
+            search.query.pop();
+            search.found = self.history_find(&search.query, self.history.len());
+            self.history_search_show(search);
+        }
+    }
+
+    fn history_find(&self, query: &str, before: usize) -> Option<usize> {
+        match query.is_empty() {
+            true => None,
+            false => self.history.find_backwards(query, before),
+        }
+    }
+
+    fn history_search_show(&mut self, search: HistorySearch) {
+        let input = match search.found {
+            Some(index) => self.history_entry(index),
+            None => search.original_input.clone(),
+        };
+        self.set_input(input);
+        self.history_search = Some(search);
+    }
+
+    /// Finish the search keeping the found entry as the current input
+    pub fn history_search_accept(&mut self) {
+        self.history_search = None;
+    }
+
+    /// Finish the search restoring the input written before the search
+    pub fn history_search_cancel(&mut self) {
+        if let Some(search) = self.history_search.take() {
+            self.set_input(search.original_input);
+        }
+    }
+
     pub fn reset_input(&mut self) -> Option<String> {
+        self.history_position = None;
+        self.history_draft.clear();
         if !self.input.is_empty() {
             self.input_cursor = 0;
-            return Some(self.input.drain(..).collect());
+            let input: String = self.input.drain(..).collect();
+            self.history.push(input.clone()).report_if_err(self);
+            return Some(input)
         }
         None
     }
 
     pub fn add_message(&mut self, message: ChatMessage) {
+        if self.scroll_messages_view.is_some() {
+            self.unread_messages += 1;
+        }
         self.messages.push(message);
     }
 
     pub fn add_system_warn_message(&mut self, content: String) {
         let message_type = MessageType::System(content, SystemMessageType::Warning);
         let message = ChatMessage::new("Termchat: ".into(), message_type);
-        self.messages.push(message);
+        self.add_message(message);
     }
 
     pub fn add_system_info_message(&mut self, content: String) {
         let message_type = MessageType::System(content, SystemMessageType::Info);
         let message = ChatMessage::new("Termchat: ".into(), message_type);
-        self.messages.push(message);
+        self.add_message(message);
     }
 
     pub fn add_system_error_message(&mut self, content: String) {
         let message_type = MessageType::System(content, SystemMessageType::Error);
         let message = ChatMessage::new("Termchat: ".into(), message_type);
-        self.messages.push(message);
+        self.add_message(message);
+    }
+
+    pub fn add_file_received_message(&mut self, user: &str, file_path: PathBuf) {
+        let message_type = MessageType::FileReceived(user.into(), file_path);
+        let message = ChatMessage::new("Termchat: ".into(), message_type);
+        self.add_message(message);
+    }
+
+    pub fn add_command_output_message(&mut self, command: &str) -> usize {
+        let output = CommandOutput {
+            command: command.into(),
+            lines: Vec::new(),
+            skipped_lines: 0,
+            exit: None,
+        };
+        self.add_message(ChatMessage::new("Termchat: ".into(), MessageType::CommandOutput(output)));
+        self.messages.len() - 1
+    }
+
+    pub fn command_output_push(&mut self, index: usize, content: String, error: bool) {
+        match &mut self.messages[index].message_type {
+            MessageType::CommandOutput(output) => {
+                if output.lines.len() < CommandOutput::MAX_LINES {
+                    output.lines.push(OutputLine { content, error });
+                }
+                else {
+                    output.skipped_lines += 1;
+                }
+            }
+            _ => panic!("Must be a CommandOutput MessageType"),
+        }
+    }
+
+    /// Returns the output as text, to be shared
+    pub fn command_output_end(&mut self, index: usize, exit: String) -> String {
+        match &mut self.messages[index].message_type {
+            MessageType::CommandOutput(output) => {
+                output.exit = Some(exit);
+                output.text()
+            }
+            _ => panic!("Must be a CommandOutput MessageType"),
+        }
+    }
+
+    /// Returns the id of the new tail, or None if the file was already followed
+    pub fn start_tail(&mut self, file_path: &Path) -> Option<usize> {
+        if self.tailed_files.iter().any(|(tailed, _)| tailed == file_path) {
+            return None
+        }
+        self.last_tail_id += 1;
+        self.tailed_files.push((file_path.into(), self.last_tail_id));
+        Some(self.last_tail_id)
+    }
+
+    pub fn is_tailed(&self, id: usize) -> bool {
+        self.tailed_files.iter().any(|(_, tailed_id)| *tailed_id == id)
+    }
+
+    pub fn stop_tail(&mut self, id: usize) {
+        self.tailed_files.retain(|(_, tailed_id)| *tailed_id != id);
+    }
+
+    /// Stop following the files with that path or name, or all of them if no file is given.
+    /// Returns the files no longer followed
+    pub fn stop_tails(&mut self, file: Option<&str>) -> Vec<PathBuf> {
+        let file_path = file.and_then(|file| std::fs::canonicalize(file).ok());
+        let (stopped, kept) =
+            std::mem::take(&mut self.tailed_files).into_iter().partition(|(tailed, _)| match file {
+                Some(file) => {
+                    Some(tailed) == file_path.as_ref()
+                        || tailed.file_name().is_some_and(|name| name == file)
+                }
+                None => true,
+            });
+        self.tailed_files = kept;
+        stopped.into_iter().map(|(tailed, _)| tailed).collect()
+    }
+
+    pub fn exec_generation(&self) -> usize {
+        self.exec_generation
+    }
+
+    /// Cancel the running commands of `?exec`
+    pub fn cancel_execs(&mut self) {
+        self.exec_generation += 1;
     }
 
     pub fn add_progress_message(&mut self, file_name: &str, total: u64) -> usize {
@@ -236,6 +868,35 @@ impl State {
         self.messages.len() - 1
     }
 
+    pub fn add_file_transfer(&mut self, id: usize, file_name: &str, size: u64) {
+        let index = self.add_progress_message(file_name, size);
+        self.sending_files.insert(id, index);
+    }
+
+    pub fn file_transfer_progress(&mut self, id: usize, bytes: u64) {
+        if let Some(index) = self.sending_files.get(&id) {
+            self.progress_message_update(*index, bytes);
+        }
+    }
+
+    pub fn file_transfer_end(&mut self, id: usize) {
+        self.sending_files.remove(&id);
+    }
+
+    /// The file was sent, it is completed even if no progress was notified, as an empty file
+    pub fn file_transfer_sent(&mut self, id: usize) {
+        if let Some(index) = self.sending_files.remove(&id) {
+            if let MessageType::Progress(progress) = &mut self.messages[index].message_type {
+                *progress = ProgressState::Completed;
+            }
+        }
+    }
+
+    /// The message is the progress of a file still being sent
+    pub fn is_sending_file(&self, index: usize) -> bool {
+        self.sending_files.values().any(|sending| *sending == index)
+    }
+
     pub fn progress_message_update(&mut self, index: usize, increment: u64) {
         match &mut self.messages[index].message_type {
             MessageType::Progress(ref mut state) => {
@@ -257,6 +918,85 @@ impl State {
         }
     }
 
+    pub fn video_panel_visible(&self) -> bool {
+        !self.windows.is_empty() && !self.video_panel_hidden
+    }
+
+    /// Screen of a terminal shared by other user
+    pub fn update_shared_terminal(&mut self, endpoint: Endpoint, screen: TermScreen) {
+        match screen {
+            TermScreen::Contents(command, rows, cols, contents) => {
+                let mut terminal = SharedTerminal::new(&command, rows, cols);
+                terminal.parser.process(&contents);
+                self.terminals.insert(endpoint, terminal);
+            }
+            TermScreen::Diff(diff) => {
+                // The changes received before the whole screen are discarded
+                if let Some(terminal) = self.terminals.get_mut(&endpoint) {
+                    terminal.parser.process(&diff);
+                }
+            }
+            TermScreen::End => {
+                self.terminals.remove(&endpoint);
+            }
+        }
+    }
+
+    /// The shared terminals with the name of its user, ours first
+    pub fn shared_terminals(&self) -> Vec<(&str, &SharedTerminal)> {
+        let own = self.own_terminal.as_ref().map(|terminal| ("me", terminal));
+        let mut others = self
+            .terminals
+            .iter()
+            .filter_map(|(endpoint, terminal)| {
+                Some((self.user_name(*endpoint)?.as_str(), terminal))
+            })
+            .collect::<Vec<_>>();
+        others.sort_by_key(|(user, _)| *user);
+        own.into_iter().chain(others).collect()
+    }
+
+    pub fn terminals_panel_visible(&self) -> bool {
+        (self.own_terminal.is_some() || !self.terminals.is_empty()) && !self.video_panel_hidden
+    }
+
+    pub fn toggle_video_panel(&mut self) {
+        self.video_panel_hidden = !self.video_panel_hidden;
+    }
+
+    pub fn connection(&self) -> Option<&ConnectionInfo> {
+        self.connection.as_ref()
+    }
+
+    pub fn set_connection(&mut self, connection: ConnectionInfo) {
+        self.connection = Some(connection);
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.lan_users.len()
+    }
+
+    pub fn start_receiving_file(&mut self, endpoint: Endpoint, file_name: &str) {
+        self.receiving_files.insert((endpoint, file_name.into()));
+    }
+
+    pub fn stop_receiving_file(&mut self, endpoint: Endpoint, file_name: &str) {
+        self.receiving_files.remove(&(endpoint, file_name.into()));
+    }
+
+    /// Files being sent or received
+    pub fn active_transfers(&self) -> usize {
+        self.sending_files.len() + self.receiving_files.len()
+    }
+
+    /// Video streams being sent or received
+    pub fn active_streams(&self) -> usize {
+        self.windows.len()
+            + self.sending_stream as usize
+            + self.terminals.len()
+            + self.own_terminal.is_some() as usize
+    }
+
     pub fn update_window(
         &mut self,
         endpoint: &Endpoint,
@@ -270,3 +1010,187 @@ impl State {
         window.data = data;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(state: &State) -> String {
+        state.input().iter().collect()
+    }
+
+    fn write(state: &mut State, text: &str) {
+        text.chars().for_each(|character| state.input_write(character));
+    }
+
+    fn state_with_history(entries: &[&str]) -> State {
+        let mut state = State::new(InputHistory::default());
+        for entry in entries {
+            write(&mut state, entry);
+            state.reset_input();
+        }
+        state
+    }
+
+    #[test]
+    fn history_search() {
+        let mut state = state_with_history(&["hello bob", "?send a.txt", "hello alice"]);
+        write(&mut state, "draft");
+        state.history_search_start();
+        "hello".chars().for_each(|character| state.history_search_write(character));
+        assert_eq!(input(&state), "hello alice");
+        // Pressing it again finds an older match
+        state.history_search_start();
+        assert_eq!(input(&state), "hello bob");
+        state.history_search_cancel();
+        assert_eq!(input(&state), "draft");
+        assert!(state.history_search().is_none());
+    }
+
+    #[test]
+    fn history_browse_keeps_the_draft() {
+        let mut state = state_with_history(&["first", "second"]);
+        write(&mut state, "draft");
+        state.input_history_previous();
+        state.input_history_previous();
+        assert_eq!(input(&state), "first");
+        state.input_history_next();
+        state.input_history_next();
+        assert_eq!(input(&state), "draft");
+    }
+
+    /// State showing 20 lines of messages in a panel of 5 rows
+    fn state_with_messages_view() -> State {
+        let mut state = State::default();
+        state.update_messages_view(20, 5);
+        state
+    }
+
+    #[test]
+    fn new_messages_followed_at_the_bottom() {
+        let mut state = state_with_messages_view();
+        state.add_system_info_message("new".into());
+        state.update_messages_view(21, 5);
+        assert_eq!(state.scroll_messages_view(), None);
+        assert_eq!(state.unread_messages(), 0);
+    }
+
+    #[test]
+    fn scrolled_up_counts_unread_messages() {
+        let mut state = state_with_messages_view();
+        state.messages_scroll(ScrollMovement::Up);
+        assert_eq!(state.scroll_messages_view(), Some(14));
+        state.add_system_info_message("first".into());
+        state.add_system_info_message("second".into());
+        state.update_messages_view(22, 5);
+        assert_eq!(state.scroll_messages_view(), Some(14));
+        assert_eq!(state.unread_messages(), 2);
+    }
+
+    #[test]
+    fn page_scroll_clamped() {
+        let mut state = state_with_messages_view();
+        // A page keeps one line of the previous one
+        state.messages_scroll(ScrollMovement::PageUp);
+        assert_eq!(state.scroll_messages_view(), Some(11));
+        state.messages_scroll(ScrollMovement::Top);
+        state.messages_scroll(ScrollMovement::PageUp);
+        assert_eq!(state.scroll_messages_view(), Some(0));
+        for _ in 0..3 {
+            state.messages_scroll(ScrollMovement::PageDown);
+        }
+        assert_eq!(state.scroll_messages_view(), Some(12));
+        state.messages_scroll(ScrollMovement::PageDown);
+        assert_eq!(state.scroll_messages_view(), None);
+        state.messages_scroll(ScrollMovement::PageDown);
+        assert_eq!(state.scroll_messages_view(), None);
+    }
+
+    #[test]
+    fn unread_messages_reset_at_the_bottom() {
+        let mut state = state_with_messages_view();
+        state.messages_scroll(ScrollMovement::Up);
+        state.add_system_info_message("new".into());
+        assert_eq!(state.unread_messages(), 1);
+        state.messages_scroll(ScrollMovement::Down);
+        assert_eq!(state.scroll_messages_view(), None);
+        assert_eq!(state.unread_messages(), 0);
+
+        // Also when the panel grows to show the last message
+        state.messages_scroll(ScrollMovement::PageUp);
+        state.add_system_info_message("new".into());
+        state.update_messages_view(21, 8);
+        assert_eq!(state.scroll_messages_view(), Some(11));
+        assert_eq!(state.unread_messages(), 1);
+        state.update_messages_view(21, 10);
+        assert_eq!(state.scroll_messages_view(), None);
+        assert_eq!(state.unread_messages(), 0);
+    }
+
+    fn endpoint() -> Endpoint {
+        use message_io::network::{Transport};
+        let (handler, _) = message_io::node::split::<()>();
+        // An udp connection only binds a local socket
+        handler.network().connect(Transport::Udp, "127.0.0.1:5877").unwrap().0
+    }
+
+    fn screen_text(state: &State, endpoint: Endpoint) -> String {
+        state.terminals[&endpoint].parser.screen().contents()
+    }
+
+    #[test]
+    fn shared_terminal_updates() {
+        let mut state = State::default();
+        let endpoint = endpoint();
+        // The changes before the whole screen are discarded
+        state.update_shared_terminal(endpoint, TermScreen::Diff(b"lost".to_vec()));
+        assert!(state.terminals.is_empty());
+
+        let contents = TermScreen::Contents("top".into(), 24, 80, b"hello".to_vec());
+        state.update_shared_terminal(endpoint, contents);
+        assert_eq!(state.terminals[&endpoint].command, "top");
+        assert_eq!(screen_text(&state, endpoint), "hello");
+
+        state.update_shared_terminal(endpoint, TermScreen::Diff(b" world".to_vec()));
+        assert_eq!(screen_text(&state, endpoint), "hello world");
+
+        state.update_shared_terminal(endpoint, TermScreen::End);
+        assert!(state.terminals.is_empty());
+    }
+
+    #[test]
+    fn shared_terminal_size_limits() {
+        let mut state = State::default();
+        let endpoint = endpoint();
+        let contents = TermScreen::Contents("top".into(), 1000, 1000, Vec::new());
+        state.update_shared_terminal(endpoint, contents);
+        assert_eq!(state.terminals[&endpoint].parser.screen().size(), (200, 500));
+    }
+
+    #[test]
+    fn consecutive_kills_are_joined() {
+        let mut state = State::default();
+        state.input_insert("one two three");
+        state.input_kill(KillMovement::WordLeft);
+        state.input_kill(KillMovement::WordLeft);
+        assert_eq!(input(&state), "one ");
+        state.input_yank();
+        assert_eq!(input(&state), "one two three");
+    }
+
+    #[test]
+    fn kills_separated_by_edition() {
+        let mut state = State::default();
+        state.input_insert("one two");
+        state.input_kill(KillMovement::WordLeft);
+        // Back to the same cursor and length as after the kill
+        state.input_write('x');
+        state.input_remove_previous();
+        state.input_kill(KillMovement::WordLeft);
+        assert_eq!(input(&state), "");
+        state.input_yank();
+        assert_eq!(input(&state), "one ");
+        state.input_yank_pop();
+        assert_eq!(input(&state), "two");
+    }
+}
@@ -1,18 +1,26 @@
 use resize::{Pixel::RGB8, px::RGB};
 use resize::Type::Lanczos3;
-use crate::{config::Theme, state::Window};
+use crate::{
+    config::{Theme, PanelLayout, VideoPanelPosition},
+    state::{Window, SharedTerminal},
+};
 
-use super::state::{ProgressState, State, MessageType, SystemMessageType};
+use super::state::{
+    ChatMessage, CommandOutput, ProgressState, State, MessageType, SystemMessageType, TailLines,
+};
 use super::commands::{CommandManager};
 use super::util::{split_each};
+use super::vi::{ViMode};
 
 use tui::backend::CrosstermBackend;
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, Paragraph, Wrap};
+use tui::widgets::{Block, Borders, Clear, Paragraph};
 use tui::{Frame};
 
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 use std::io::Write;
 
 pub fn draw(
@@ -20,25 +28,101 @@ pub fn draw(
     state: &State,
     chunk: Rect,
     theme: &Theme,
-) {
+    layout: &PanelLayout,
+) -> MessagesLayout {
+    let input_height = input_panel_height(state, chunk, layout);
+    let status_height = layout.status_bar as u16;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0), Constraint::Length(6)].as_ref())
+        .constraints(
+            [
+                Constraint::Min(0),
+                Constraint::Length(input_height),
+                Constraint::Length(status_height),
+            ]
+            .as_ref(),
+        )
         .split(chunk);
 
-    let upper_chunk = chunks[0];
-    if !state.windows.is_empty() {
-        let upper_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(0), Constraint::Length(30)].as_ref())
-            .split(upper_chunk);
-        draw_messages_panel(frame, state, upper_chunks[0], theme);
-        draw_video_panel(frame, state, upper_chunks[1]);
+    let upper_chunk = match state.terminals_panel_visible() {
+        true => {
+            let height = terminals_panel_height(state, chunks[0], layout);
+            let upper_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(height), Constraint::Min(0)].as_ref())
+                .split(chunks[0]);
+            draw_terminals_panel(frame, state, upper_chunks[0], layout);
+            upper_chunks[1]
+        }
+        false => chunks[0],
+    };
+    let video_panel = match state.video_panel_visible() {
+        true => layout.video_panel,
+        false => VideoPanelPosition::Hidden,
+    };
+    let messages_layout = match video_panel {
+        VideoPanelPosition::Right | VideoPanelPosition::Bottom => {
+            let (direction, size) = match video_panel {
+                VideoPanelPosition::Right => (Direction::Horizontal, layout.video_panel_width),
+                _ => (Direction::Vertical, layout.video_panel_height),
+            };
+            let upper_chunks = Layout::default()
+                .direction(direction)
+                .constraints([Constraint::Min(0), Constraint::Length(size)].as_ref())
+                .split(upper_chunk);
+            draw_video_panel(frame, state, upper_chunks[1], layout);
+            draw_messages_panel(frame, state, upper_chunks[0], theme, layout)
+        }
+        VideoPanelPosition::Hidden => draw_messages_panel(frame, state, upper_chunk, theme, layout),
+    };
+    draw_input_panel(frame, state, chunks[1], theme, layout);
+    draw_completion_popup(frame, state, chunks[1], theme);
+    if layout.status_bar {
+        draw_status_bar(frame, state, chunks[2], theme);
     }
-    else {
-        draw_messages_panel(frame, state, chunks[0], theme);
+    messages_layout
+}
+
+/// Block surrounding a panel, without borders in compact mode
+fn panel_block<'a>(title: Option<String>, layout: &PanelLayout) -> Block<'a> {
+    let block = match layout.compact {
+        true => Block::default(),
+        false => Block::default().borders(Borders::ALL),
+    };
+    match title {
+        Some(title) => {
+            block.title(Span::styled(title, Style::default().add_modifier(Modifier::BOLD)))
+        }
+        None => block,
+    }
+}
+
+/// Where the messages were drawn, used to find the message under the mouse
+/// and to know the size of the messages view
+#[derive(Default)]
+pub struct MessagesLayout {
+    area: Rect,
+    // Index of the message drawn in each visible row
+    rows: Vec<usize>,
+    total_lines: usize,
+}
+
+impl MessagesLayout {
+    pub fn total_lines(&self) -> usize {
+        self.total_lines
+    }
+
+    pub fn height(&self) -> usize {
+        self.area.height as usize
+    }
+
+    pub fn message_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.area;
+        if column < area.x || column >= area.x + area.width || row < area.y {
+            return None
+        }
+        self.rows.get((row - area.y) as usize).copied()
     }
-    draw_input_panel(frame, state, chunks[1], theme);
 }
 
 fn draw_messages_panel(
@@ -46,72 +130,294 @@ fn draw_messages_panel(
     state: &State,
     chunk: Rect,
     theme: &Theme,
-) {
-    let message_colors = &theme.message_colors;
+    layout: &PanelLayout,
+) -> MessagesLayout {
+    // In compact mode the title is removed to show one more row of messages
+    let title = match layout.compact {
+        true => None,
+        false => Some(String::from("LAN Room")),
+    };
+    let block = panel_block(title, layout);
+    let area = block.inner(chunk);
 
-    let messages = state
-        .messages()
-        .iter()
-        .rev()
-        .map(|message| {
-            let color = if let Some(id) = state.users_id().get(&message.user) {
-                message_colors[id % message_colors.len()]
-            }
-            else {
-                theme.my_user_color
-            };
-            let date = message.date.format("%H:%M:%S ").to_string();
-            match &message.message_type {
-                MessageType::Connection => Spans::from(vec![
-                    Span::styled(date, Style::default().fg(theme.date_color)),
-                    Span::styled(&message.user, Style::default().fg(color)),
-                    Span::styled(" is online", Style::default().fg(color)),
-                ]),
-                MessageType::Disconnection => Spans::from(vec![
-                    Span::styled(date, Style::default().fg(theme.date_color)),
-                    Span::styled(&message.user, Style::default().fg(color)),
-                    Span::styled(" is offline", Style::default().fg(color)),
-                ]),
-                MessageType::Text(content) => {
-                    let mut ui_message = vec![
-                        Span::styled(date, Style::default().fg(theme.date_color)),
-                        Span::styled(&message.user, Style::default().fg(color)),
-                        Span::styled(": ", Style::default().fg(color)),
-                    ];
-                    ui_message.extend(parse_content(content, theme));
-                    Spans::from(ui_message)
-                }
-                MessageType::System(content, msg_type) => {
-                    let (user_color, content_color) = match msg_type {
-                        SystemMessageType::Info => theme.system_info_color,
-                        SystemMessageType::Warning => theme.system_warning_color,
-                        SystemMessageType::Error => theme.system_error_color,
-                    };
-                    Spans::from(vec![
-                        Span::styled(date, Style::default().fg(theme.date_color)),
-                        Span::styled(&message.user, Style::default().fg(user_color)),
-                        Span::styled(content, Style::default().fg(content_color)),
-                    ])
-                }
-                MessageType::Progress(state) => {
-                    Spans::from(add_progress_bar(chunk.width, state, theme))
+    // The lines are wrapped here instead of by the paragraph to know the rows of each message
+    let mut lines = Vec::new();
+    let mut rows = Vec::new();
+    for (index, message) in state.messages().iter().enumerate() {
+        let selected = state.selected_message() == Some(index);
+        for mut line in message_lines(message, selected, state, chunk.width, theme) {
+            if selected {
+                for span in line.0.iter_mut() {
+                    span.style = span.style.add_modifier(Modifier::REVERSED);
                 }
             }
-        })
-        .collect::<Vec<_>>();
+            for wrapped in wrap_spans(line, area.width as usize) {
+                lines.push(wrapped);
+                rows.push(index);
+            }
+        }
+    }
 
-    let messages_panel = Paragraph::new(messages)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(Span::styled("LAN Room", Style::default().add_modifier(Modifier::BOLD))),
-        )
+    let total_lines = lines.len();
+    let last_top = total_lines.saturating_sub(area.height as usize);
+    let top = state.scroll_messages_view().unwrap_or(last_top).min(last_top);
+    let rows = rows.into_iter().skip(top).take(area.height as usize).collect();
+
+    let messages_panel = Paragraph::new(lines)
+        .block(block)
         .style(Style::default().fg(theme.chat_panel_color))
         .alignment(Alignment::Left)
-        .scroll((state.scroll_messages_view() as u16, 0))
-        .wrap(Wrap { trim: false });
+        .scroll((top as u16, 0));
 
     frame.render_widget(messages_panel, chunk);
+
+    if state.unread_messages() > 0 {
+        draw_unread_marker(frame, state.unread_messages(), area, theme);
+    }
+
+    MessagesLayout { area, rows, total_lines }
+}
+
+/// Shown over the last row of the messages while the user reads older messages
+fn draw_unread_marker(
+    frame: &mut Frame<CrosstermBackend<impl Write>>,
+    unread: usize,
+    area: Rect,
+    theme: &Theme,
+) {
+    let text = match unread {
+        1 => String::from(" 1 new message ↓ "),
+        _ => format!(" {} new messages ↓ ", unread),
+    };
+    let width = (text.width() as u16).min(area.width);
+    if area.height == 0 {
+        return
+    }
+    let chunk = Rect::new(area.x + area.width - width, area.y + area.height - 1, width, 1);
+    let marker = Paragraph::new(Span::styled(
+        text,
+        Style::default().fg(theme.chat_panel_color).add_modifier(Modifier::REVERSED),
+    ));
+    frame.render_widget(marker, chunk);
+}
+
+fn draw_status_bar(
+    frame: &mut Frame<CrosstermBackend<impl Write>>,
+    state: &State,
+    chunk: Rect,
+    theme: &Theme,
+) {
+    let plural = |count: usize, name: &str| match count {
+        1 => format!("1 {}", name),
+        _ => format!("{} {}s", count, name),
+    };
+
+    let mut fields = Vec::new();
+    if let Some(connection) = state.connection() {
+        fields.push(connection.user_name.clone());
+        fields.push(format!("tcp {}", connection.server_addr));
+        fields.push(format!("discovery {}", connection.discovery_addr));
+    }
+    fields.push(plural(state.peer_count(), "peer"));
+    fields.push(plural(state.active_transfers(), "transfer"));
+    fields.push(plural(state.active_streams(), "stream"));
+    fields.push(format!("{} unread", state.unread_messages()));
+
+    let status = format!(" {}", fields.join(" │ "));
+    let status_bar = Paragraph::new(Span::raw(status))
+        .style(Style::default().fg(theme.chat_panel_color).add_modifier(Modifier::REVERSED));
+    frame.render_widget(status_bar, chunk);
+}
+
+fn message_lines<'a>(
+    message: &'a ChatMessage,
+    selected: bool,
+    state: &State,
+    panel_width: u16,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let message_colors = &theme.message_colors;
+    let color = if let Some(id) = state.users_id().get(&message.user) {
+        message_colors[id % message_colors.len()]
+    }
+    else {
+        theme.my_user_color
+    };
+    let date = message.date.format("%H:%M:%S ").to_string();
+    match &message.message_type {
+        MessageType::Connection => vec![Spans::from(vec![
+            Span::styled(date, Style::default().fg(theme.date_color)),
+            Span::styled(&message.user, Style::default().fg(color)),
+            Span::styled(" is online", Style::default().fg(color)),
+        ])],
+        MessageType::Disconnection => vec![Spans::from(vec![
+            Span::styled(date, Style::default().fg(theme.date_color)),
+            Span::styled(&message.user, Style::default().fg(color)),
+            Span::styled(" is offline", Style::default().fg(color)),
+        ])],
+        MessageType::Text(content) => {
+            let mut lines = content.split('\n');
+            let indent = date.width() + message.user.width() + 2;
+            let mut ui_message = vec![
+                Span::styled(date, Style::default().fg(theme.date_color)),
+                Span::styled(&message.user, Style::default().fg(color)),
+                Span::styled(": ", Style::default().fg(color)),
+            ];
+            ui_message.extend(parse_content(lines.next().unwrap_or_default(), theme));
+            let mut ui_lines = vec![Spans::from(ui_message)];
+            ui_lines.extend(continuation_lines(lines, indent, Style::default()));
+            ui_lines
+        }
+        MessageType::System(content, msg_type) => {
+            system_message_lines(date, &message.user, content.into(), msg_type, theme)
+        }
+        MessageType::FileReceived(user, path) => {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let mut content =
+                format!("Successfully received file '{}' from user '{}'!", file_name, user);
+            if selected {
+                content.push_str(&format!("\nSaved in '{}'", path.display()));
+            }
+            system_message_lines(date, &message.user, content, &SystemMessageType::Info, theme)
+        }
+        MessageType::Progress(state) => {
+            vec![Spans::from(add_progress_bar(panel_width, state, theme))]
+        }
+        MessageType::CommandOutput(output) => command_output_lines(date, message, output, theme),
+        MessageType::TailLines(tail) => tail_lines(date, message, tail, color, theme),
+    }
+}
+
+/// The name of the followed file followed by its new lines
+fn tail_lines<'a>(
+    date: String,
+    message: &'a ChatMessage,
+    tail: &'a TailLines,
+    user_color: Color,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let indent = date.width() + message.user.width() + 2;
+    let mut header = vec![
+        Span::styled(date, Style::default().fg(theme.date_color)),
+        Span::styled(&message.user, Style::default().fg(user_color)),
+        Span::styled(": ", Style::default().fg(user_color)),
+        Span::styled(format!("[{}]", tail.file_name), Style::default().fg(theme.command_color)),
+    ];
+    if tail.skipped_lines > 0 {
+        let skipped = format!(" {} lines skipped", tail.skipped_lines);
+        header.push(Span::styled(skipped, Style::default().fg(theme.date_color)));
+    }
+    let mut ui_lines = vec![Spans::from(header)];
+    ui_lines.extend(
+        tail.lines
+            .iter()
+            .map(|line| Spans::from(vec![Span::raw(" ".repeat(indent)), Span::raw(line.as_str())])),
+    );
+    ui_lines
+}
+
+/// The command line followed by its output, with the errors and the exit highlighted
+fn command_output_lines<'a>(
+    date: String,
+    message: &'a ChatMessage,
+    output: &'a CommandOutput,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let (user_color, _) = theme.system_info_color;
+    let (_, error_color) = theme.system_error_color;
+    let indent = date.width() + message.user.width();
+    let mut ui_lines = vec![Spans::from(vec![
+        Span::styled(date, Style::default().fg(theme.date_color)),
+        Span::styled(&message.user, Style::default().fg(user_color)),
+        Span::styled(format!("$ {}", output.command), Style::default().fg(theme.command_color)),
+    ])];
+    ui_lines.extend(output.lines.iter().map(|line| {
+        let style = match line.error {
+            true => Style::default().fg(error_color),
+            false => Style::default(),
+        };
+        Spans::from(vec![Span::raw(" ".repeat(indent)), Span::styled(&line.content, style)])
+    }));
+    let mut status = Vec::new();
+    if output.skipped_lines > 0 {
+        status.push(format!("{} more lines", output.skipped_lines));
+    }
+    status.push(output.exit.clone().unwrap_or_else(|| String::from("running...")));
+    ui_lines.push(Spans::from(vec![
+        Span::raw(" ".repeat(indent)),
+        Span::styled(format!("[{}]", status.join(", ")), Style::default().fg(theme.date_color)),
+    ]));
+    ui_lines
+}
+
+fn system_message_lines<'a>(
+    date: String,
+    user: &'a str,
+    content: String,
+    msg_type: &SystemMessageType,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let (user_color, content_color) = match msg_type {
+        SystemMessageType::Info => theme.system_info_color,
+        SystemMessageType::Warning => theme.system_warning_color,
+        SystemMessageType::Error => theme.system_error_color,
+    };
+    let indent = date.width() + user.width();
+    let mut lines = content.split('\n').map(String::from).collect::<Vec<_>>().into_iter();
+    let mut ui_lines = vec![Spans::from(vec![
+        Span::styled(date, Style::default().fg(theme.date_color)),
+        Span::styled(user, Style::default().fg(user_color)),
+        Span::styled(lines.next().unwrap_or_default(), Style::default().fg(content_color)),
+    ])];
+    ui_lines.extend(lines.map(|line| {
+        Spans::from(vec![
+            Span::raw(" ".repeat(indent)),
+            Span::styled(line, Style::default().fg(content_color)),
+        ])
+    }));
+    ui_lines
+}
+
+/// Split a line in rows that fit in the width, keeping the style of each part
+fn wrap_spans(spans: Spans<'_>, width: usize) -> Vec<Spans<'_>> {
+    if width == 0 {
+        return vec![spans]
+    }
+
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut row_width = 0;
+    for span in spans.0 {
+        let mut content = String::new();
+        for character in span.content.chars() {
+            let char_width = character.width().unwrap_or(0);
+            if row_width + char_width > width && row_width > 0 {
+                if !content.is_empty() {
+                    row.push(Span::styled(std::mem::take(&mut content), span.style));
+                }
+                rows.push(Spans::from(std::mem::take(&mut row)));
+                row_width = 0;
+            }
+            content.push(character);
+            row_width += char_width;
+        }
+        if !content.is_empty() {
+            row.push(Span::styled(content, span.style));
+        }
+    }
+    rows.push(Spans::from(row));
+    rows
+}
+
+/// Lines after the first one of a multi-line message, aligned with the first line content
+fn continuation_lines<'a>(
+    lines: impl Iterator<Item = &'a str> + 'a,
+    indent: usize,
+    style: Style,
+) -> impl Iterator<Item = Spans<'a>> + 'a {
+    lines.map(move |line| {
+        Spans::from(vec![Span::raw(" ".repeat(indent)), Span::styled(line, style)])
+    })
 }
 
 fn add_progress_bar<'a>(
@@ -120,7 +426,7 @@ fn add_progress_bar<'a>(
     theme: &Theme,
 ) -> Vec<Span<'a>> {
     let color = theme.progress_bar_color;
-    let width = (panel_width - 20) as usize;
+    let width = panel_width.saturating_sub(20) as usize;
 
     let (title, ui_current, ui_remaining) = match progress {
         ProgressState::Started(_) => ("Pending: ", 0, width),
@@ -165,13 +471,48 @@ fn parse_content<'a>(content: &'a str, theme: &Theme) -> Vec<Span<'a>> {
     }
 }
 
+/// The input panel grows with the written lines, up to half of the available space
+fn input_panel_height(state: &State, chunk: Rect, layout: &PanelLayout) -> u16 {
+    // Rows and columns taken by the block around the input: the borders, or only the title
+    let (block_width, block_height) = match layout.compact {
+        true => (0, 1),
+        false => (2, 2),
+    };
+    let min_height = layout.input_height.max(block_height + 1);
+    let inner_width = chunk.width.saturating_sub(block_width).max(1) as usize;
+    let input = state.input().iter().collect::<String>();
+    let rows =
+        split_each(input, inner_width).len().max(state.ui_input_cursor(inner_width).1 as usize + 1);
+    let max_height = (chunk.height / 2).max(min_height);
+    (rows as u16 + block_height).clamp(min_height, max_height)
+}
+
 fn draw_input_panel(
     frame: &mut Frame<CrosstermBackend<impl Write>>,
     state: &State,
     chunk: Rect,
     theme: &Theme,
+    layout: &PanelLayout,
 ) {
-    let inner_width = (chunk.width - 2) as usize;
+    let title = match state.history_search() {
+        Some(search) if search.is_found() => format!("(reverse-i-search)`{}'", search.query),
+        Some(search) => format!("(failing reverse-i-search)`{}'", search.query),
+        None => match state.vi_mode() {
+            Some(ViMode::Normal) => String::from("Your message [NORMAL]"),
+            Some(ViMode::Insert) => String::from("Your message [INSERT]"),
+            None => String::from("Your message"),
+        },
+    };
+
+    // The title is kept in compact mode to separate the input from the messages
+    let block = panel_block(Some(title), layout);
+    let area = block.inner(chunk);
+    let inner_width = area.width.max(1) as usize;
+    let inner_height = area.height;
+
+    // Scroll the input to keep the cursor always visible
+    let input_cursor = state.ui_input_cursor(inner_width);
+    let scroll = (input_cursor.1 + 1).saturating_sub(inner_height);
 
     let input = state.input().iter().collect::<String>();
     let input = split_each(input, inner_width)
@@ -180,23 +521,68 @@ fn draw_input_panel(
         .collect::<Vec<_>>();
 
     let input_panel = Paragraph::new(input)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(Span::styled("Your message", Style::default().add_modifier(Modifier::BOLD))),
-        )
+        .block(block)
         .style(Style::default().fg(theme.input_panel_color))
-        .alignment(Alignment::Left);
+        .alignment(Alignment::Left)
+        .scroll((scroll, 0));
 
     frame.render_widget(input_panel, chunk);
 
-    let input_cursor = state.ui_input_cursor(inner_width);
-    frame.set_cursor(chunk.x + 1 + input_cursor.0, chunk.y + 1 + input_cursor.1)
+    frame.set_cursor(area.x + input_cursor.0, area.y + input_cursor.1 - scroll)
 }
 
-fn draw_video_panel(frame: &mut Frame<CrosstermBackend<impl Write>>, state: &State, chunk: Rect) {
+/// Show the completion candidates just above the input panel
+fn draw_completion_popup(
+    frame: &mut Frame<CrosstermBackend<impl Write>>,
+    state: &State,
+    input_chunk: Rect,
+    theme: &Theme,
+) {
+    const MAX_VISIBLE: usize = 8;
+
+    let completion = match state.completion() {
+        Some(completion) => completion,
+        None => return,
+    };
+
+    let candidates = completion.candidates();
+    let visible = candidates.len().min(MAX_VISIBLE);
+    let first = (completion.selected() + 1).saturating_sub(visible);
+    let width = candidates.iter().map(|candidate| candidate.width()).max().unwrap_or(0) + 2;
+    let width = (width as u16).min(input_chunk.width);
+    let height = (visible as u16 + 2).min(input_chunk.y);
+    let chunk = Rect::new(input_chunk.x, input_chunk.y - height, width, height);
+
+    let lines = candidates
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(index, candidate)| {
+            let style = match index == completion.selected() {
+                true => Style::default().add_modifier(Modifier::REVERSED),
+                false => Style::default(),
+            };
+            Spans::from(Span::styled(candidate.trim_end(), style))
+        })
+        .collect::<Vec<_>>();
+
+    let popup = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL))
+        .style(Style::default().fg(theme.input_panel_color));
+
+    frame.render_widget(Clear, chunk);
+    frame.render_widget(popup, chunk);
+}
+
+fn draw_video_panel(
+    frame: &mut Frame<CrosstermBackend<impl Write>>,
+    state: &State,
+    chunk: Rect,
+    layout: &PanelLayout,
+) {
     let windows = state.windows.values().collect();
-    let fb = FrameBuffer::new(windows).block(Block::default().borders(Borders::ALL));
+    let fb = FrameBuffer::new(windows).block(panel_block(None, layout));
     frame.render_widget(fb, chunk);
 }
 #[derive(Default)]
@@ -259,3 +645,89 @@ impl tui::widgets::Widget for FrameBuffer<'_> {
         }
     }
 }
+
+/// The rows of the biggest shared terminal, up to half of the screen
+fn terminals_panel_height(state: &State, chunk: Rect, layout: &PanelLayout) -> u16 {
+    let borders = if layout.compact { 0 } else { 2 };
+    let rows = state
+        .shared_terminals()
+        .iter()
+        .map(|(_, terminal)| terminal.parser.screen().size().0)
+        .max()
+        .unwrap_or(0);
+    (rows + borders).min(chunk.height / 2)
+}
+
+fn draw_terminals_panel(
+    frame: &mut Frame<CrosstermBackend<impl Write>>,
+    state: &State,
+    chunk: Rect,
+    layout: &PanelLayout,
+) {
+    let terminals = state.shared_terminals();
+    let constraints = vec![Constraint::Ratio(1, terminals.len() as u32); terminals.len()];
+    let chunks =
+        Layout::default().direction(Direction::Horizontal).constraints(constraints).split(chunk);
+
+    for ((user, terminal), chunk) in terminals.into_iter().zip(chunks) {
+        let title = format!("{}: {}", user, terminal.command);
+        let view = TerminalView { terminal, block: panel_block(Some(title), layout) };
+        frame.render_widget(view, chunk);
+    }
+}
+
+/// Draws the screen of a shared terminal, cropped if it does not fit in the area
+struct TerminalView<'a> {
+    terminal: &'a SharedTerminal,
+    block: Block<'a>,
+}
+
+impl tui::widgets::Widget for TerminalView<'_> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        let inner_area = self.block.inner(area);
+        self.block.render(area, buf);
+
+        let screen = self.terminal.parser.screen();
+        let (rows, cols) = screen.size();
+        for row in 0..rows.min(inner_area.height) {
+            for col in 0..cols.min(inner_area.width) {
+                let cell = match screen.cell(row, col) {
+                    Some(cell) if !cell.is_wide_continuation() => cell,
+                    _ => continue,
+                };
+                let mut modifier = Modifier::empty();
+                if cell.bold() {
+                    modifier |= Modifier::BOLD;
+                }
+                if cell.italic() {
+                    modifier |= Modifier::ITALIC;
+                }
+                if cell.underline() {
+                    modifier |= Modifier::UNDERLINED;
+                }
+                if cell.inverse() {
+                    modifier |= Modifier::REVERSED;
+                }
+                let style = Style::default()
+                    .fg(terminal_color(cell.fgcolor()))
+                    .bg(terminal_color(cell.bgcolor()))
+                    .add_modifier(modifier);
+                let symbol = match cell.has_contents() {
+                    true => cell.contents(),
+                    false => " ",
+                };
+                buf.get_mut(inner_area.x + col, inner_area.y + row)
+                    .set_symbol(symbol)
+                    .set_style(style);
+            }
+        }
+    }
+}
+
+fn terminal_color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(index) => Color::Indexed(index),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
@@ -1,12 +1,18 @@
-// split messages to fit the width of the ui panel
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+// split messages to fit the width of the ui panel, new lines always start a new row
+#[cfg(feature = "tui")]
 pub fn split_each(input: String, width: usize) -> Vec<String> {
+    use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
     let mut splitted = Vec::with_capacity(input.width() / width);
     let mut row = String::new();
 
     let mut index = 0;
 
     for current_char in input.chars() {
+        if current_char == '\n' {
+            splitted.push(std::mem::take(&mut row));
+            index = 0;
+            continue
+        }
         if (index != 0 && index == width) || index + current_char.width().unwrap_or(0) > width {
             splitted.push(std::mem::take(&mut row));
             index = 0;
@@ -27,6 +33,7 @@ pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 //TODO: Should send the file even if some endpoint of send_all gives an error.
+#[cfg(feature = "tui")]
 pub fn stringify_sendall_errors(e: Vec<(message_io::network::Endpoint, std::io::Error)>) -> String {
     let mut out = String::new();
     for (endpoint, error) in e {
@@ -41,8 +48,10 @@ pub fn stringify_sendall_errors(e: Vec<(message_io::network::Endpoint, std::io::
     out
 }
 
+#[cfg(feature = "tui")]
 use crate::state::State;
 /// Trait for reporting Recoverable errors/ Infos to the user
+#[cfg(feature = "tui")]
 pub trait Reportable: Sized {
     fn report_if_err(self, _state: &mut State) {
         unimplemented!()
@@ -58,6 +67,7 @@ pub trait Reportable: Sized {
     }
 }
 
+#[cfg(feature = "tui")]
 impl Reportable for Result<()> {
     fn report_if_err(self, state: &mut State) {
         if let Err(e) = self {
@@ -66,6 +76,7 @@ impl Reportable for Result<()> {
     }
 }
 
+#[cfg(feature = "tui")]
 impl Reportable for std::result::Result<(), Vec<(message_io::network::Endpoint, std::io::Error)>> {
     fn report_if_err(self, state: &mut State) {
         if let Err(e) = self {
@@ -74,12 +85,14 @@ impl Reportable for std::result::Result<(), Vec<(message_io::network::Endpoint,
     }
 }
 
+#[cfg(feature = "tui")]
 impl Reportable for Box<dyn std::error::Error + Send + Sync> {
     fn report_err(self, state: &mut State) {
         self.to_string().report_err(state);
     }
 }
 
+#[cfg(feature = "tui")]
 impl Reportable for String {
     fn report_err(self, state: &mut State) {
         state.add_system_error_message(self);
@@ -0,0 +1,233 @@
+use crate::state::{State, CursorMovement, KillMovement, ScrollMovement};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ViMode {
+    Normal,
+    Insert,
+}
+
+/// Modal editing state, it only exists if the vi editing mode is enabled
+pub struct ViState {
+    pub mode: ViMode,
+    // Operator waiting for its motion, as the first `d` of `dd`
+    pending: Option<char>,
+}
+
+impl Default for ViState {
+    fn default() -> Self {
+        // Starts writing as the non modal editing does
+        ViState { mode: ViMode::Insert, pending: None }
+    }
+}
+
+/// Leave the insert mode. As vim does, the cursor goes back over the last inserted char.
+pub fn enter_normal_mode(state: &mut State) {
+    if let Some(vi) = state.vi_mut() {
+        vi.mode = ViMode::Normal;
+        vi.pending = None;
+        state.input_move_cursor(CursorMovement::Left);
+    }
+}
+
+fn enter_insert_mode(state: &mut State) {
+    if let Some(vi) = state.vi_mut() {
+        vi.mode = ViMode::Insert;
+    }
+}
+
+/// Process a char typed in normal mode
+pub fn process_normal_char(state: &mut State, character: char) {
+    let pending = match state.vi_mut() {
+        Some(vi) => vi.pending.take(),
+        None => return,
+    };
+
+    match (pending, character) {
+        // Motions
+        (None, 'h') => state.input_move_cursor(CursorMovement::Left),
+        (None, 'l') => state.input_move_cursor(CursorMovement::Right),
+        (None, '0') | (None, '^') => state.input_move_cursor(CursorMovement::Start),
+        (None, '$') => state.input_move_cursor(CursorMovement::End),
+        (None, 'w') => state.input_move_cursor(CursorMovement::NextWord),
+        (None, 'b') => state.input_move_cursor(CursorMovement::WordLeft),
+        (None, 'e') => {
+            state.input_move_cursor(CursorMovement::Right);
+            state.input_move_cursor(CursorMovement::WordRight);
+            state.input_move_cursor(CursorMovement::Left);
+        }
+
+        // Insertion
+        (None, 'i') => enter_insert_mode(state),
+        (None, 'a') => {
+            state.input_move_cursor(CursorMovement::Right);
+            enter_insert_mode(state);
+        }
+        (None, 'I') => {
+            state.input_move_cursor(CursorMovement::Start);
+            enter_insert_mode(state);
+        }
+        (None, 'A') => {
+            state.input_move_cursor(CursorMovement::End);
+            enter_insert_mode(state);
+        }
+
+        // Edition
+        (None, 'x') => state.input_remove(),
+        (None, 'D') => state.input_kill(KillMovement::End),
+        (None, 'C') => {
+            state.input_kill(KillMovement::End);
+            enter_insert_mode(state);
+        }
+        (None, 'p') => {
+            state.input_move_cursor(CursorMovement::Right);
+            state.input_yank();
+        }
+        (None, 'P') => state.input_yank(),
+        (None, operator @ 'd') | (None, operator @ 'c') | (None, operator @ 'g') => {
+            if let Some(vi) = state.vi_mut() {
+                vi.pending = Some(operator);
+            }
+        }
+        (Some('g'), 'g') => state.messages_scroll(ScrollMovement::Top),
+        (Some(operator @ 'd'), motion) | (Some(operator @ 'c'), motion) => {
+            let movement = match (operator, motion) {
+                ('d', 'd') | ('c', 'c') => KillMovement::All,
+                // As vim does, `cw` changes only until the end of the word
+                ('c', 'w') | (_, 'e') => KillMovement::WordRight,
+                (_, 'w') => KillMovement::NextWord,
+                (_, 'b') => KillMovement::WordLeft,
+                (_, '$') => KillMovement::End,
+                (_, '0') => KillMovement::Start,
+                _ => return,
+            };
+            state.input_kill(movement);
+            if operator == 'c' {
+                enter_insert_mode(state);
+            }
+        }
+
+        // Messages panel and history
+        (None, 'j') => state.messages_scroll(ScrollMovement::Down),
+        (None, 'k') => state.messages_scroll(ScrollMovement::Up),
+        (None, 'G') => state.messages_scroll(ScrollMovement::Bottom),
+        (None, '/') => state.history_search_start(),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{InputHistory};
+
+    /// State in normal mode after writing `text`
+    fn state_with_input(text: &str) -> State {
+        let mut state = State::new(InputHistory::default());
+        state.enable_vi_mode(true);
+        text.chars().for_each(|character| state.input_write(character));
+        enter_normal_mode(&mut state);
+        state
+    }
+
+    fn type_keys(state: &mut State, keys: &str) {
+        keys.chars().for_each(|character| process_normal_char(state, character));
+    }
+
+    fn input(state: &State) -> String {
+        state.input().iter().collect()
+    }
+
+    fn cursor(state: &State) -> usize {
+        state.input_before_cursor().len()
+    }
+
+    #[test]
+    fn normal_mode_over_last_char() {
+        let state = state_with_input("one two three");
+        assert!(state.vi_mode() == Some(ViMode::Normal));
+        assert_eq!(cursor(&state), 12);
+    }
+
+    #[test]
+    fn motions() {
+        let mut state = state_with_input("one two three");
+        let expected = [('0', 0), ('l', 1), ('w', 4), ('e', 6), ('w', 8), ('b', 4), ('h', 3)];
+        for (key, position) in expected {
+            process_normal_char(&mut state, key);
+            assert_eq!(cursor(&state), position, "after '{}'", key);
+        }
+        type_keys(&mut state, "$");
+        assert_eq!(cursor(&state), 13);
+        assert_eq!(input(&state), "one two three");
+    }
+
+    #[test]
+    fn delete_operators() {
+        let mut state = state_with_input("one two three");
+        type_keys(&mut state, "0dw");
+        assert_eq!(input(&state), "two three");
+        type_keys(&mut state, "x");
+        assert_eq!(input(&state), "wo three");
+        type_keys(&mut state, "wD");
+        assert_eq!(input(&state), "wo ");
+        type_keys(&mut state, "dd");
+        assert_eq!(input(&state), "");
+        assert!(state.vi_mode() == Some(ViMode::Normal));
+    }
+
+    #[test]
+    fn unknown_motion_cancels_the_operator() {
+        let mut state = state_with_input("one two");
+        type_keys(&mut state, "0dzw");
+        assert_eq!(input(&state), "one two");
+        assert_eq!(cursor(&state), 4);
+    }
+
+    #[test]
+    fn change_operators() {
+        let mut state = state_with_input("one two three");
+        type_keys(&mut state, "0cw");
+        // Unlike `dw`, the whitespace after the word is kept
+        assert_eq!(input(&state), " two three");
+        assert!(state.vi_mode() == Some(ViMode::Insert));
+
+        let mut state = state_with_input("one two three");
+        type_keys(&mut state, "cc");
+        assert_eq!(input(&state), "");
+        assert!(state.vi_mode() == Some(ViMode::Insert));
+    }
+
+    #[test]
+    fn paste_the_killed_text() {
+        let mut state = state_with_input("one two three");
+        type_keys(&mut state, "0dwwP");
+        assert_eq!(input(&state), "two one three");
+    }
+
+    #[test]
+    fn insert_modes() {
+        let mut state = state_with_input("one two");
+        type_keys(&mut state, "I");
+        assert!(state.vi_mode() == Some(ViMode::Insert));
+        assert_eq!(cursor(&state), 0);
+
+        enter_normal_mode(&mut state);
+        type_keys(&mut state, "A");
+        assert_eq!(cursor(&state), 7);
+
+        enter_normal_mode(&mut state);
+        type_keys(&mut state, "0a");
+        assert_eq!(cursor(&state), 1);
+        state.input_write('x');
+        assert_eq!(input(&state), "oxne two");
+    }
+
+    #[test]
+    fn disabled_vi_mode_ignores_normal_chars() {
+        let mut state = State::new(InputHistory::default());
+        "one".chars().for_each(|character| state.input_write(character));
+        type_keys(&mut state, "0dd");
+        assert_eq!(input(&state), "one");
+        assert!(state.vi_mode().is_none());
+    }
+}
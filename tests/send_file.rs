@@ -2,6 +2,8 @@
 
 use termchat::application::{Application, Signal};
 use termchat::config::Config;
+use termchat::frontend::{InputEvent};
+use termchat::frontend::tui::{TuiFrontend};
 
 use message_io::node::{NodeHandler};
 
@@ -46,26 +48,26 @@ fn send_file() {
 fn test_user(config: Config) -> (NodeHandler<Signal>, std::thread::JoinHandle<()>) {
     let (tx, rx) = std::sync::mpsc::channel();
     let t = std::thread::spawn(move || {
-        let mut app = Application::new(&config).unwrap();
+        let mut app = Application::new(config).unwrap();
         tx.send(app.node_handler()).unwrap();
-        app.run(std::io::sink()).unwrap();
+        app.run(TuiFrontend::new(std::io::sink()).unwrap()).unwrap();
     });
     (rx.recv().unwrap(), t)
 }
 
 fn input(handler: &mut NodeHandler<Signal>, s: &str) {
     for c in s.chars() {
-        handler.signals().send(Signal::Terminal(crossterm::event::Event::Key(
+        handler.signals().send(Signal::Input(InputEvent::Terminal(crossterm::event::Event::Key(
             crossterm::event::KeyEvent {
                 code: crossterm::event::KeyCode::Char(c),
                 modifiers: crossterm::event::KeyModifiers::NONE,
             },
-        )));
+        ))));
     }
-    handler.signals().send(Signal::Terminal(crossterm::event::Event::Key(
+    handler.signals().send(Signal::Input(InputEvent::Terminal(crossterm::event::Event::Key(
         crossterm::event::KeyEvent {
             code: crossterm::event::KeyCode::Enter,
             modifiers: crossterm::event::KeyModifiers::NONE,
         },
-    )));
+    ))));
 }